@@ -0,0 +1,52 @@
+// Volume envelope of the square and noise channels (NRx2)
+#[derive(Default)]
+pub(super) struct Envelope {
+	initial_volume: u8,
+	increase: bool,
+	period: u8,
+
+	volume: u8,
+	timer: u8,
+}
+
+impl Envelope {
+	pub fn volume(&self) -> u8 { self.volume }
+
+	// The DAC is off when the upper 5 bits of NRx2 are cleared.
+	pub fn dac_enabled(&self) -> bool {
+		self.initial_volume != 0 || self.increase
+	}
+
+	pub fn write(&mut self, value: u8) {
+		self.initial_volume = value >> 4;
+		self.increase = (value & 0x08) != 0;
+		self.period = value & 0x07;
+	}
+
+	pub fn trigger(&mut self) {
+		self.volume = self.initial_volume;
+		self.timer = self.reload_value();
+	}
+
+	pub fn clock(&mut self) {
+		if self.period == 0 {
+			return;
+		}
+
+		self.timer = self.timer.saturating_sub(1);
+		if self.timer == 0 {
+			self.timer = self.reload_value();
+
+			if self.increase && self.volume < 15 {
+				self.volume += 1;
+			} else if !self.increase && self.volume > 0 {
+				self.volume -= 1;
+			}
+		}
+	}
+
+	// A period of 0 is treated as 8.
+	fn reload_value(&self) -> u8 {
+		if self.period == 0 { 8 } else { self.period }
+	}
+}
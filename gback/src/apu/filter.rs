@@ -0,0 +1,48 @@
+use crate::apu::{CLOCK_RATE, HighPass};
+
+// The capacitor on the console's output, which removes the DC offset
+// of the DACs
+pub(super) struct HighPassFilter<const N: usize> {
+	model: HighPass,
+	// Fraction of the charge kept by the capacitor after each sample
+	charge_factor: f32,
+	capacitor: [f32; N],
+}
+
+impl<const N: usize> HighPassFilter<N> {
+	pub fn new(model: HighPass, sample_rate: u32) -> HighPassFilter<N> {
+		let mut filter = HighPassFilter {
+			model,
+			charge_factor: 1.0,
+			capacitor: [0.0; N],
+		};
+
+		filter.set_sample_rate(sample_rate);
+		filter
+	}
+
+	pub fn set_sample_rate(&mut self, sample_rate: u32) {
+		// Charge kept after each dot
+		let factor: f64 = match self.model {
+			HighPass::None => return,
+			HighPass::Dmg => 0.999958,
+			HighPass::Cgb => 0.998943,
+		};
+
+		self.charge_factor = factor.powf(CLOCK_RATE as f64 / sample_rate as f64) as f32;
+	}
+
+	pub fn apply(&mut self, input: [f32; N]) -> [f32; N] {
+		if self.model == HighPass::None {
+			return input;
+		}
+
+		let mut output = [0.0; N];
+		for stream in 0..N {
+			output[stream] = input[stream] - self.capacitor[stream];
+			self.capacitor[stream] = input[stream] - output[stream] * self.charge_factor;
+		}
+
+		output
+	}
+}
@@ -0,0 +1,60 @@
+// Silences a channel after a set time, when enabled
+#[derive(Default)]
+pub(super) struct Length {
+	counter: u16,
+	max: u16,
+	enabled: bool,
+}
+
+impl Length {
+	pub fn new(max: u16) -> Length {
+		Length {
+			counter: 0,
+			max,
+			enabled: false,
+		}
+	}
+
+	pub fn load(&mut self, value: u8) {
+		self.counter = self.max - value as u16;
+	}
+
+	// On DMG, the counter is kept when the APU is powered off.
+	pub fn power_off(&mut self) {
+		self.enabled = false;
+	}
+
+	// Returns true when the counter has just run out.
+	pub fn clock(&mut self) -> bool {
+		if self.enabled && self.counter > 0 {
+			self.counter -= 1;
+			return self.counter == 0;
+		}
+
+		false
+	}
+
+	// Handles a write to NRx4. When the last frame sequencer step clocked
+	// the length counters, enabling them clocks them once more. Returns
+	// true if the channel must be disabled.
+	pub fn write_control(&mut self, enabled: bool, trigger: bool, extra_clock: bool) -> bool {
+		let was_enabled = self.enabled;
+		self.enabled = enabled;
+
+		let mut expired = false;
+		if extra_clock && !was_enabled && enabled && self.counter > 0 {
+			self.counter -= 1;
+			expired = self.counter == 0 && !trigger;
+		}
+
+		if trigger && self.counter == 0 {
+			self.counter = self.max;
+
+			if enabled && extra_clock {
+				self.counter -= 1;
+			}
+		}
+
+		expired
+	}
+}
@@ -1,10 +1,412 @@
-#[derive(Default)]
+mod envelope;
+mod length;
+mod square;
+mod wave;
+mod noise;
+mod resampler;
+mod filter;
+
+use square::Square;
+use wave::Wave;
+use noise::Noise;
+use resampler::Resampler;
+use filter::HighPassFilter;
+
+// Rate at which the APU is clocked, in Hz
+const CLOCK_RATE: u32 = 4_194_304;
+
+/// Default rate of the samples sent to the platform, in Hz.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// High-pass filter applied to the output, as done by the capacitors
+/// of each console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighPass {
+	None,
+	Dmg,
+	Cgb,
+}
+
+/// One of the four sound channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+	Square1 = 0,
+	Square2 = 1,
+	Wave = 2,
+	Noise = 3,
+}
+
+impl Channel {
+	pub const ALL: [Channel; 4] = [Channel::Square1, Channel::Square2, Channel::Wave, Channel::Noise];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AudioConfig {
+	/// Initial rate of the samples sent to the platform, in Hz.
+	pub sample_rate: u32,
+	pub high_pass: HighPass,
+	/// Also produce a mono stream for each channel, before panning and muting.
+	pub channel_streams: bool,
+}
+
+impl Default for AudioConfig {
+	fn default() -> Self {
+		Self {
+			sample_rate: SAMPLE_RATE,
+			high_pass: HighPass::Dmg,
+			channel_streams: false,
+		}
+	}
+}
+
+// Bits of 0xFF10-0xFF2F which always read as 1
+const READ_MASKS: [u8; 0x20] = [
+	0x80, 0x3F, 0x00, 0xFF, 0xBF,
+	0xFF, 0x3F, 0x00, 0xFF, 0xBF,
+	0x7F, 0xFF, 0x9F, 0xFF, 0xBF,
+	0xFF, 0xFF, 0x00, 0x00, 0xBF,
+	0x00, 0x00, 0x70,
+	0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// Each channel on its own, as the output of its DAC
+struct ChannelStreams {
+	level: [f32; 4],
+	resampler: Resampler<4>,
+	filter: HighPassFilter<4>,
+	samples: [Vec<f32>; 4],
+}
+
 pub struct APU {
+	powered: bool,
+	square1: Square,
+	square2: Square,
+	wave: Wave,
+	noise: Noise,
+
+	// Frame sequencer, clocked by DIV
+	frame_step: u8,
+	div_signal: bool,
+
+	// Last values written to 0xFF10-0xFF2F
+	registers: [u8; 0x20],
 
+	// Output level of both sides, as last sent to the resampler
+	level: [f32; 2],
+	resampler: Resampler<2>,
+	filter: HighPassFilter<2>,
+	// Interleaved stereo samples, waiting to be sent to the platform
+	samples: Vec<f32>,
+	streams: Option<ChannelStreams>,
+
+	// Channels left out of the mixed output
+	muted: [bool; 4],
+	solo: Option<Channel>,
 }
 
 impl APU {
-	pub fn write_io_register(&mut self, _addr: u16, _value: u8) {
+	pub fn new(config: AudioConfig) -> APU {
+		APU {
+			powered: false,
+			square1: Square::new(true),
+			square2: Square::new(false),
+			wave: Wave::new(),
+			noise: Noise::new(),
+
+			frame_step: 0,
+			div_signal: false,
 
+			registers: [0; 0x20],
+
+			level: [0.0; 2],
+			resampler: Resampler::new(config.sample_rate),
+			filter: HighPassFilter::new(config.high_pass, config.sample_rate),
+			samples: vec![],
+			streams: if config.channel_streams {
+				Some(ChannelStreams {
+					level: [0.0; 4],
+					resampler: Resampler::new(config.sample_rate),
+					filter: HighPassFilter::new(config.high_pass, config.sample_rate),
+					samples: Default::default(),
+				})
+			} else {
+				None
+			},
+
+			muted: [false; 4],
+			solo: None,
+		}
+	}
+
+	pub fn samples(&self) -> &[f32] { &self.samples }
+
+	pub fn channel_samples(&self, channel: Channel) -> Option<&[f32]> {
+		self.streams.as_ref().map(|streams| &streams.samples[channel as usize][..])
+	}
+
+	pub fn ack_samples(&mut self) {
+		self.samples.clear();
+
+		if let Some(streams) = &mut self.streams {
+			streams.samples.iter_mut().for_each(Vec::clear);
+		}
+	}
+
+	pub fn set_sample_rate(&mut self, rate: u32) {
+		self.resampler.set_sample_rate(rate);
+		self.filter.set_sample_rate(rate);
+
+		if let Some(streams) = &mut self.streams {
+			streams.resampler.set_sample_rate(rate);
+			streams.filter.set_sample_rate(rate);
+		}
 	}
-}
\ No newline at end of file
+
+	// Sets which channels are playing, as in the low bits of NR52, for
+	// the state left by the boot ROM.
+	pub fn set_channel_status(&mut self, status: u8) {
+		self.square1.enabled = (status & 0x01) != 0;
+		self.square2.enabled = (status & 0x02) != 0;
+		self.wave.enabled = (status & 0x04) != 0;
+		self.noise.enabled = (status & 0x08) != 0;
+	}
+
+	pub fn is_muted(&self, channel: Channel) -> bool { self.muted[channel as usize] }
+	pub fn set_muted(&mut self, channel: Channel, muted: bool) { self.muted[channel as usize] = muted; }
+
+	pub fn solo(&self) -> Option<Channel> { self.solo }
+	pub fn set_solo(&mut self, channel: Option<Channel>) { self.solo = channel; }
+
+	// A soloed channel is heard even when muted.
+	fn is_audible(&self, channel: usize) -> bool {
+		match self.solo {
+			Some(solo) => solo as usize == channel,
+			None => !self.muted[channel],
+		}
+	}
+
+	// `div_signal` is the DIV bit clocking the frame sequencer.
+	pub fn spend(&mut self, cycles: u32, div_signal: bool) {
+		if self.powered {
+			// The channels are run up to their next step at most, so that
+			// every change of the output is placed at the right dot.
+			let mut cycles = cycles;
+			while cycles > 0 {
+				let step = cycles
+					.min(self.square1.timer())
+					.min(self.square2.timer())
+					.min(self.wave.timer())
+					.min(self.noise.timer());
+
+				self.square1.spend(step);
+				self.square2.spend(step);
+				self.wave.spend(step);
+				self.noise.spend(step);
+
+				self.advance_resamplers(step);
+				self.update_level();
+				cycles -= step;
+			}
+
+			if self.div_signal && !div_signal {
+				self.step_frame_sequencer();
+				self.update_level();
+			}
+		} else {
+			self.advance_resamplers(cycles);
+		}
+
+		self.div_signal = div_signal;
+
+		let samples = &mut self.samples;
+		let filter = &mut self.filter;
+		self.resampler.drain(|frame| {
+			let [left, right] = filter.apply(frame);
+			samples.push(left);
+			samples.push(right);
+		});
+
+		if let Some(streams) = &mut self.streams {
+			let samples = &mut streams.samples;
+			let filter = &mut streams.filter;
+			streams.resampler.drain(|frame| {
+				for (samples, &value) in samples.iter_mut().zip(filter.apply(frame).iter()) {
+					samples.push(value);
+				}
+			});
+		}
+	}
+
+	fn advance_resamplers(&mut self, cycles: u32) {
+		self.resampler.advance(cycles);
+
+		if let Some(streams) = &mut self.streams {
+			streams.resampler.advance(cycles);
+		}
+	}
+
+	// Lengths are clocked at 256 Hz, the sweep at 128 Hz
+	// and envelopes at 64 Hz.
+	fn step_frame_sequencer(&mut self) {
+		match self.frame_step {
+			0 | 4 => self.clock_lengths(),
+			2 | 6 => {
+				self.clock_lengths();
+				self.square1.clock_sweep();
+			},
+			7 => {
+				self.square1.clock_envelope();
+				self.square2.clock_envelope();
+				self.noise.clock_envelope();
+			},
+			_ => {},
+		}
+
+		self.frame_step = (self.frame_step + 1) & 0x07;
+	}
+
+	fn clock_lengths(&mut self) {
+		self.square1.clock_length();
+		self.square2.clock_length();
+		self.wave.clock_length();
+		self.noise.clock_length();
+	}
+
+	fn update_level(&mut self) {
+		let outputs = [
+			dac_output(self.square1.output(), self.square1.dac_enabled()),
+			dac_output(self.square2.output(), self.square2.dac_enabled()),
+			dac_output(self.wave.output(), self.wave.dac_enabled()),
+			dac_output(self.noise.output(), self.noise.dac_enabled()),
+		];
+
+		let level = self.mix(&outputs);
+		if level != self.level {
+			self.resampler.add_delta([level[0] - self.level[0], level[1] - self.level[1]]);
+			self.level = level;
+		}
+
+		if let Some(streams) = &mut self.streams {
+			if outputs != streams.level {
+				let mut delta = [0.0; 4];
+				for (channel, delta) in delta.iter_mut().enumerate() {
+					*delta = outputs[channel] - streams.level[channel];
+				}
+
+				streams.resampler.add_delta(delta);
+				streams.level = outputs;
+			}
+		}
+	}
+
+	fn mix(&self, outputs: &[f32; 4]) -> [f32; 2] {
+		// NR51 selects the channels sent to each side.
+		let panning = self.registers[0x15];
+		let mut left = 0.0;
+		let mut right = 0.0;
+
+		for (channel, output) in outputs.iter().enumerate() {
+			if !self.is_audible(channel) {
+				continue;
+			}
+
+			if (panning & (0x10 << channel)) != 0 {
+				left += output;
+			}
+
+			if (panning & (0x01 << channel)) != 0 {
+				right += output;
+			}
+		}
+
+		// NR50 volumes go from 1/8 to 8/8.
+		let volume = self.registers[0x14];
+		let left_volume = (((volume >> 4) & 0x07) + 1) as f32 / 8.0;
+		let right_volume = ((volume & 0x07) + 1) as f32 / 8.0;
+
+		[left / 4.0 * left_volume, right / 4.0 * right_volume]
+	}
+
+	fn set_power(&mut self, powered: bool) {
+		if self.powered && !powered {
+			self.registers = [0; 0x20];
+			self.square1.power_off();
+			self.square2.power_off();
+			self.wave.power_off();
+			self.noise.power_off();
+		} else if !self.powered && powered {
+			self.frame_step = 0;
+		}
+
+		self.powered = powered;
+	}
+
+	pub fn read_io_register(&self, addr: u16) -> u8 {
+		match addr {
+			0xff26 => {
+				0x70 |
+				(self.powered as u8) << 7 |
+				(self.noise.enabled as u8) << 3 |
+				(self.wave.enabled as u8) << 2 |
+				(self.square2.enabled as u8) << 1 |
+				(self.square1.enabled as u8)
+			},
+			0xff10..=0xff2f => self.registers[(addr - 0xff10) as usize] | READ_MASKS[(addr - 0xff10) as usize],
+			0xff30..=0xff3f => self.wave.read_ram(addr),
+			_ => unreachable!(),
+		}
+	}
+
+	pub fn write_io_register(&mut self, addr: u16, value: u8) {
+		self.write_register(addr, value);
+		self.update_level();
+	}
+
+	fn write_register(&mut self, addr: u16, value: u8) {
+		// Enabling a length counter clocks it once more when the last
+		// frame sequencer step clocked the lengths.
+		let extra_length_clock = (self.frame_step & 1) != 0;
+
+		match addr {
+			0xff26 => self.set_power((value & 0x80) != 0),
+			0xff30..=0xff3f => self.wave.write_ram(addr, value),
+
+			// While powered off, only the lengths can be written, on DMG.
+			0xff11 if !self.powered => self.square1.length.load(value & 0x3F),
+			0xff16 if !self.powered => self.square2.length.load(value & 0x3F),
+			0xff1b if !self.powered => self.wave.length.load(value),
+			0xff20 if !self.powered => self.noise.length.load(value & 0x3F),
+			0xff10..=0xff2f if !self.powered => {},
+
+			0xff10..=0xff2f => {
+				self.registers[(addr - 0xff10) as usize] = value;
+
+				match addr {
+					0xff10..=0xff14 => self.square1.write_register(addr - 0xff10, value, extra_length_clock),
+					0xff15..=0xff19 => self.square2.write_register(addr - 0xff15, value, extra_length_clock),
+					0xff1a..=0xff1e => self.wave.write_register(addr - 0xff1a, value, extra_length_clock),
+					0xff1f..=0xff23 => self.noise.write_register(addr - 0xff1f, value, extra_length_clock),
+					// NR50 and NR51 are only used when mixing
+					_ => {},
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+}
+
+// Converts a digital output to an analog one between -1 and 1. A DAC
+// that is turned off outputs nothing.
+fn dac_output(output: u8, dac_enabled: bool) -> f32 {
+	if dac_enabled {
+		output as f32 / 7.5 - 1.0
+	} else {
+		0.0
+	}
+}
+
+impl Default for APU {
+	fn default() -> Self {
+		APU::new(Default::default())
+	}
+}
@@ -0,0 +1,121 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length::Length;
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Noise channel 4, driven by a linear feedback shift register
+#[derive(Default)]
+pub(super) struct Noise {
+	pub enabled: bool,
+	pub length: Length,
+	envelope: Envelope,
+
+	shift: u8,
+	// Short mode, where the LFSR is 7 bits long instead of 15
+	short: bool,
+	divisor: u8,
+
+	timer: u32,
+	lfsr: u16,
+}
+
+impl Noise {
+	pub fn new() -> Noise {
+		Noise {
+			length: Length::new(64),
+			..Default::default()
+		}
+	}
+
+	// Everything but the length counter is reset.
+	pub fn power_off(&mut self) {
+		let mut length = std::mem::take(&mut self.length);
+		length.power_off();
+
+		*self = Noise { length, ..Noise::new() };
+	}
+
+	pub fn dac_enabled(&self) -> bool { self.envelope.dac_enabled() }
+
+	// Current digital output, between 0 and 15
+	pub fn output(&self) -> u8 {
+		if !self.enabled {
+			return 0;
+		}
+
+		(!self.lfsr & 1) as u8 * self.envelope.volume()
+	}
+
+	// Dots left before the next step
+	pub fn timer(&self) -> u32 { self.timer }
+
+	pub fn spend(&mut self, cycles: u32) {
+		let mut cycles = cycles;
+
+		while cycles >= self.timer {
+			cycles -= self.timer;
+			self.timer = self.period();
+
+			let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+			self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+
+			if self.short {
+				self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+			}
+		}
+
+		self.timer -= cycles;
+	}
+
+	fn period(&self) -> u32 {
+		DIVISORS[self.divisor as usize] << self.shift
+	}
+
+	pub fn clock_length(&mut self) {
+		if self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	pub fn clock_envelope(&mut self) {
+		self.envelope.clock();
+	}
+
+	pub fn write_register(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+		match register {
+			// NR40 doesn't exist
+			0 => {},
+			1 => self.length.load(value & 0x3F),
+			2 => {
+				self.envelope.write(value);
+
+				if !self.dac_enabled() {
+					self.enabled = false;
+				}
+			},
+			3 => {
+				self.shift = value >> 4;
+				self.short = (value & 0x08) != 0;
+				self.divisor = value & 0x07;
+			},
+			4 => {
+				let trigger = (value & 0x80) != 0;
+				if self.length.write_control((value & 0x40) != 0, trigger, extra_length_clock) {
+					self.enabled = false;
+				}
+
+				if trigger {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled();
+		self.timer = self.period();
+		self.lfsr = 0x7FFF;
+		self.envelope.trigger();
+	}
+}
@@ -0,0 +1,117 @@
+use std::f64::consts::PI;
+use crate::apu::CLOCK_RATE;
+
+// Number of positions between two output samples a change can be placed at
+const PHASES: usize = 32;
+// Length of the kernel, in output samples
+const TAPS: usize = 16;
+// Cutoff frequency, relative to the output rate
+const CUTOFF: f64 = 0.45;
+
+// Band-limited synthesis of the APU output. Each change of the output
+// level is added as a band-limited step instead of being sampled, which
+// avoids the aliasing of the 4 MiHz square waves.
+pub(super) struct Resampler<const N: usize> {
+	// Output samples per dot
+	ratio: f64,
+	// Position of the current dot in the buffer, in output samples
+	position: f64,
+
+	kernel: Vec<[f32; TAPS]>,
+	// Differences between consecutive output samples, for each stream
+	buffer: Vec<[f32; N]>,
+	level: [f32; N],
+}
+
+impl<const N: usize> Resampler<N> {
+	pub fn new(sample_rate: u32) -> Resampler<N> {
+		// Windowed-sinc impulses, delayed by half the kernel so that they
+		// don't reach back before the current sample.
+		let kernel = (0..PHASES).map(|phase| {
+			let offset = phase as f64 / PHASES as f64;
+			let mut taps = [0.0; TAPS];
+
+			for (tap, value) in taps.iter_mut().enumerate() {
+				let x = tap as f64 - (TAPS / 2) as f64 - offset;
+				*value = (sinc(2.0 * CUTOFF * x) * blackman(x)) as f32;
+			}
+
+			// Each step has to end up at the level it was given.
+			let sum: f32 = taps.iter().sum();
+			for value in taps.iter_mut() {
+				*value /= sum;
+			}
+
+			taps
+		}).collect();
+
+		Resampler {
+			ratio: sample_rate as f64 / CLOCK_RATE as f64,
+			position: 0.0,
+			kernel,
+			buffer: vec![],
+			level: [0.0; N],
+		}
+	}
+
+	pub fn set_sample_rate(&mut self, sample_rate: u32) {
+		self.ratio = sample_rate as f64 / CLOCK_RATE as f64;
+	}
+
+	pub fn advance(&mut self, cycles: u32) {
+		self.position += cycles as f64 * self.ratio;
+	}
+
+	// Changes the output level by `delta` at the current dot.
+	pub fn add_delta(&mut self, delta: [f32; N]) {
+		let index = self.position as usize;
+		let phase = (((self.position - index as f64) * PHASES as f64) as usize).min(PHASES - 1);
+
+		if self.buffer.len() < index + TAPS {
+			self.buffer.resize(index + TAPS, [0.0; N]);
+		}
+
+		for (sample, &tap) in self.buffer[index..].iter_mut().zip(self.kernel[phase].iter()) {
+			for (value, delta) in sample.iter_mut().zip(delta.iter()) {
+				*value += delta * tap;
+			}
+		}
+	}
+
+	// Sends every sample that can't be changed anymore to `output`.
+	pub fn drain<F: FnMut([f32; N])>(&mut self, mut output: F) {
+		let done = self.position as usize;
+
+		if self.buffer.len() < done {
+			self.buffer.resize(done, [0.0; N]);
+		}
+
+		for delta in self.buffer.drain(..done) {
+			for (level, delta) in self.level.iter_mut().zip(delta.iter()) {
+				*level += delta;
+			}
+
+			output(self.level);
+		}
+
+		self.position -= done as f64;
+	}
+}
+
+fn sinc(x: f64) -> f64 {
+	if x == 0.0 {
+		1.0
+	} else {
+		(PI * x).sin() / (PI * x)
+	}
+}
+
+fn blackman(x: f64) -> f64 {
+	let width = TAPS as f64;
+
+	if x.abs() > width / 2.0 {
+		return 0.0;
+	}
+
+	0.42 + 0.5 * (2.0 * PI * x / width).cos() + 0.08 * (4.0 * PI * x / width).cos()
+}
@@ -0,0 +1,206 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length::Length;
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+	[0, 0, 0, 0, 0, 0, 0, 1],
+	[1, 0, 0, 0, 0, 0, 0, 1],
+	[1, 0, 0, 0, 0, 1, 1, 1],
+	[0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+// Frequency sweep, only present on channel 1 (NR10)
+#[derive(Default)]
+struct Sweep {
+	period: u8,
+	negate: bool,
+	shift: u8,
+
+	enabled: bool,
+	timer: u8,
+	shadow: u16,
+	// Clearing the negate bit after a subtraction was made disables the channel.
+	negate_used: bool,
+}
+
+impl Sweep {
+	// A period of 0 is treated as 8.
+	fn reload_value(&self) -> u8 {
+		if self.period == 0 { 8 } else { self.period }
+	}
+
+	// Computes the next frequency, or None if it overflows.
+	fn next_frequency(&mut self) -> Option<u16> {
+		let delta = self.shadow >> self.shift;
+		let frequency = if self.negate {
+			self.negate_used = true;
+			self.shadow.wrapping_sub(delta)
+		} else {
+			self.shadow + delta
+		};
+
+		if frequency > 2047 { None } else { Some(frequency) }
+	}
+}
+
+// Square channels 1 and 2
+#[derive(Default)]
+pub(super) struct Square {
+	pub enabled: bool,
+	sweep: Option<Sweep>,
+	pub length: Length,
+	envelope: Envelope,
+
+	duty: u8,
+	frequency: u16,
+	timer: u32,
+	duty_step: u8,
+}
+
+impl Square {
+	pub fn new(has_sweep: bool) -> Square {
+		Square {
+			sweep: if has_sweep { Some(Default::default()) } else { None },
+			length: Length::new(64),
+			..Default::default()
+		}
+	}
+
+	// Everything but the length counter is reset.
+	pub fn power_off(&mut self) {
+		let mut length = std::mem::take(&mut self.length);
+		length.power_off();
+
+		*self = Square { length, ..Square::new(self.sweep.is_some()) };
+	}
+
+	pub fn dac_enabled(&self) -> bool { self.envelope.dac_enabled() }
+
+	// Current digital output, between 0 and 15
+	pub fn output(&self) -> u8 {
+		if !self.enabled {
+			return 0;
+		}
+
+		DUTY_CYCLES[self.duty as usize][self.duty_step as usize] * self.envelope.volume()
+	}
+
+	// Dots left before the next step
+	pub fn timer(&self) -> u32 { self.timer }
+
+	pub fn spend(&mut self, cycles: u32) {
+		let mut cycles = cycles;
+
+		while cycles >= self.timer {
+			cycles -= self.timer;
+			self.timer = self.period();
+			self.duty_step = (self.duty_step + 1) & 0x07;
+		}
+
+		self.timer -= cycles;
+	}
+
+	fn period(&self) -> u32 {
+		(2048 - self.frequency as u32) * 4
+	}
+
+	pub fn clock_length(&mut self) {
+		if self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	pub fn clock_envelope(&mut self) {
+		self.envelope.clock();
+	}
+
+	pub fn clock_sweep(&mut self) {
+		let sweep = match &mut self.sweep {
+			Some(sweep) => sweep,
+			None => return,
+		};
+
+		sweep.timer = sweep.timer.saturating_sub(1);
+		if sweep.timer > 0 {
+			return;
+		}
+
+		sweep.timer = sweep.reload_value();
+		if !sweep.enabled || sweep.period == 0 {
+			return;
+		}
+
+		match sweep.next_frequency() {
+			Some(frequency) if sweep.shift != 0 => {
+				self.frequency = frequency;
+				sweep.shadow = frequency;
+
+				// The new frequency is checked for overflow once more.
+				if sweep.next_frequency().is_none() {
+					self.enabled = false;
+				}
+			},
+			Some(_) => {},
+			None => self.enabled = false,
+		}
+	}
+
+	pub fn write_register(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+		match register {
+			0 => {
+				if let Some(sweep) = &mut self.sweep {
+					let negate = (value & 0x08) != 0;
+
+					if sweep.negate && !negate && sweep.negate_used {
+						self.enabled = false;
+					}
+
+					sweep.period = (value >> 4) & 0x07;
+					sweep.negate = negate;
+					sweep.shift = value & 0x07;
+				}
+			},
+			1 => {
+				self.duty = value >> 6;
+				self.length.load(value & 0x3F);
+			},
+			2 => {
+				self.envelope.write(value);
+
+				if !self.dac_enabled() {
+					self.enabled = false;
+				}
+			},
+			3 => self.frequency = (self.frequency & 0x700) | value as u16,
+			4 => {
+				self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+
+				let trigger = (value & 0x80) != 0;
+				if self.length.write_control((value & 0x40) != 0, trigger, extra_length_clock) {
+					self.enabled = false;
+				}
+
+				if trigger {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled();
+		self.timer = self.period();
+		self.envelope.trigger();
+
+		if let Some(sweep) = &mut self.sweep {
+			sweep.shadow = self.frequency;
+			sweep.timer = sweep.reload_value();
+			sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+			sweep.negate_used = false;
+
+			if sweep.shift != 0 && sweep.next_frequency().is_none() {
+				self.enabled = false;
+			}
+		}
+	}
+}
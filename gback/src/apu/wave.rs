@@ -0,0 +1,136 @@
+use crate::apu::length::Length;
+
+// Wave channel 3, playing 32 4-bit samples from wave RAM
+#[derive(Default)]
+pub(super) struct Wave {
+	pub enabled: bool,
+	pub length: Length,
+	dac_enabled: bool,
+	// Right shift applied to the samples, 4 muting the channel
+	volume_shift: u8,
+
+	frequency: u16,
+	timer: u32,
+	position: u8,
+	sample: u8,
+
+	ram: [u8; 16],
+}
+
+impl Wave {
+	pub fn new() -> Wave {
+		Wave {
+			length: Length::new(256),
+			volume_shift: 4,
+			..Default::default()
+		}
+	}
+
+	// Everything but the length counter and wave RAM is reset.
+	pub fn power_off(&mut self) {
+		let mut length = std::mem::take(&mut self.length);
+		length.power_off();
+
+		*self = Wave { length, ram: self.ram, ..Wave::new() };
+	}
+
+	pub fn dac_enabled(&self) -> bool { self.dac_enabled }
+
+	// Current digital output, between 0 and 15
+	pub fn output(&self) -> u8 {
+		if !self.enabled {
+			return 0;
+		}
+
+		self.sample >> self.volume_shift
+	}
+
+	// Dots left before the next step
+	pub fn timer(&self) -> u32 { self.timer }
+
+	pub fn spend(&mut self, cycles: u32) {
+		let mut cycles = cycles;
+
+		while cycles >= self.timer {
+			cycles -= self.timer;
+			self.timer = self.period();
+			self.position = (self.position + 1) & 0x1F;
+
+			let byte = self.ram[(self.position >> 1) as usize];
+			self.sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+		}
+
+		self.timer -= cycles;
+	}
+
+	fn period(&self) -> u32 {
+		(2048 - self.frequency as u32) * 2
+	}
+
+	pub fn clock_length(&mut self) {
+		if self.length.clock() {
+			self.enabled = false;
+		}
+	}
+
+	// While the channel plays, the CPU can only access the byte
+	// being read by the channel.
+	pub fn read_ram(&self, addr: u16) -> u8 {
+		if self.enabled {
+			self.ram[(self.position >> 1) as usize]
+		} else {
+			self.ram[(addr & 0x0F) as usize]
+		}
+	}
+
+	pub fn write_ram(&mut self, addr: u16, value: u8) {
+		if self.enabled {
+			self.ram[(self.position >> 1) as usize] = value;
+		} else {
+			self.ram[(addr & 0x0F) as usize] = value;
+		}
+	}
+
+	pub fn write_register(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+		match register {
+			0 => {
+				self.dac_enabled = (value & 0x80) != 0;
+
+				if !self.dac_enabled {
+					self.enabled = false;
+				}
+			},
+			1 => self.length.load(value),
+			2 => {
+				self.volume_shift = match (value >> 5) & 0x03 {
+					0 => 4,
+					1 => 0,
+					2 => 1,
+					3 => 2,
+					_ => unreachable!(),
+				};
+			},
+			3 => self.frequency = (self.frequency & 0x700) | value as u16,
+			4 => {
+				self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0x07) << 8);
+
+				let trigger = (value & 0x80) != 0;
+				if self.length.write_control((value & 0x40) != 0, trigger, extra_length_clock) {
+					self.enabled = false;
+				}
+
+				if trigger {
+					self.trigger();
+				}
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	fn trigger(&mut self) {
+		self.enabled = self.dac_enabled;
+		// The first sample is only read after a short delay.
+		self.timer = self.period() + 6;
+		self.position = 0;
+	}
+}
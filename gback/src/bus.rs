@@ -1,8 +1,20 @@
-use std::io::Read;
-use crate::cartridge::Cartridge;
-use crate::ppu::PPU;
-use crate::apu::APU;
-use crate::joypad::Joypad;
+use std::io::{self, Read};
+use crate::cartridge::{Cartridge, CartridgeError, CartridgeHeader, Clock};
+use crate::ppu::{PPU, PPUAccuracy};
+use crate::apu::{APU, AudioConfig, Channel};
+use crate::joypad::{Joypad, Button};
+use crate::serial::Serial;
+use crate::Model;
+use crate::timer::Timer;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+	VBlank = 0,
+	Stat = 1,
+	Timer = 2,
+	Serial = 3,
+	Joypad = 4,
+}
 
 pub struct Bus {
 	bios: [u8; 0x100],
@@ -10,51 +22,176 @@ pub struct Bus {
 	ppu: PPU,
 	apu: APU,
 	joypad: Joypad,
+	serial: Serial,
+	timer: Timer,
 	wram: [u8; 0x2000],
 	hram: [u8; 0x80],
 
 	cycles_to_spend: u32,
 	bios_enable: bool,
 
+	// Only set when running as a CGB, which has KEY1
+	cgb: bool,
+
+	// Speed switch (KEY1)
+	double_speed: bool,
+	speed_switch_armed: bool,
+
 	// Interruptions
-	enable_vblank_irq: bool,
-	enable_stat_irq: bool,
+	int_flag: u8,
+	int_enable: u8,
 
 	// DMA
+	dma_register: u8,
 	dma_ongoing: bool,
 	dma_src: u16,
 	dma_dst: u16,
 }
 
 impl Bus {
+	pub fn with_audio_config(config: AudioConfig) -> Bus {
+		Bus {
+			apu: APU::new(config),
+			..Default::default()
+		}
+	}
+
 	pub fn is_frame_done(&self) -> bool { self.ppu.is_frame_done() }
 	pub fn ack_frame_done(&mut self) { self.ppu.ack_frame_done(); }
 	pub fn frame_buffer(&self) -> [u8; 160 * 144 * 4] { *self.ppu.buffer }
 
+	pub fn audio_samples(&self) -> &[f32] { self.apu.samples() }
+	pub fn channel_samples(&self, channel: Channel) -> Option<&[f32]> { self.apu.channel_samples(channel) }
+	pub fn ack_audio_samples(&mut self) { self.apu.ack_samples(); }
+	pub fn set_sample_rate(&mut self, rate: u32) { self.apu.set_sample_rate(rate); }
+
+	pub fn is_channel_muted(&self, channel: Channel) -> bool { self.apu.is_muted(channel) }
+	pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) { self.apu.set_muted(channel, muted); }
+	pub fn solo_channel(&self) -> Option<Channel> { self.apu.solo() }
+	pub fn set_solo_channel(&mut self, channel: Option<Channel>) { self.apu.set_solo(channel); }
+
+	pub fn request_irq(&mut self, irq: Interrupt) {
+		self.int_flag |= 1 << (irq as u8);
+	}
+
+	fn pending_irqs(&self) -> u8 {
+		self.int_flag & self.int_enable & 0x1F
+	}
+
+	// The lowest bit has the highest priority.
 	pub fn has_irq(&self) -> Option<u16> {
-		if self.ppu.has_vblank_irq() && self.enable_vblank_irq {
-			Some(0x40)
-		} else if self.ppu.has_stat_irq() && self.enable_stat_irq {
-			Some(0x48)
-		} else {
-			None
+		match self.pending_irqs() {
+			0 => None,
+			pending => Some(0x40 + ((pending.trailing_zeros() as u16) << 3)),
 		}
 	}
 
 	pub fn ack_irq(&mut self) {
-		if self.ppu.has_vblank_irq() && self.enable_vblank_irq {
-			self.ppu.ack_vblank_irq()
-		} else if self.ppu.has_stat_irq() && self.enable_stat_irq {
-			self.ppu.ack_stat_irq()
+		let pending = self.pending_irqs();
+
+		self.int_flag &= !(pending & pending.wrapping_neg());
+	}
+
+	pub fn set_ppu_accuracy(&mut self, accuracy: PPUAccuracy) { self.ppu.set_accuracy(accuracy); }
+	pub fn set_access_restrictions(&mut self, enabled: bool) { self.ppu.set_access_restrictions(enabled); }
+	pub fn rumble(&self) -> bool { self.cart.rumble() }
+	pub fn joypad_pressed(&self) -> bool { (self.joypad.read() & 0x0F) != 0x0F }
+	pub fn set_button(&mut self, button: Button, pressed: bool) { self.joypad.set_button(button, pressed); }
+
+	pub fn speed_switch_requested(&self) -> bool { self.cgb && self.speed_switch_armed }
+
+	pub fn switch_speed(&mut self) {
+		self.double_speed = !self.double_speed;
+		self.speed_switch_armed = false;
+	}
+
+	pub fn reset_divider(&mut self) {
+		self.timer.write_io_register(0xFF04, 0);
+	}
+
+	// I/O registers as the boot ROM leaves them
+	pub fn skip_boot(&mut self, model: Model) {
+		self.cgb = model == Model::Cgb;
+
+		// Only the upper byte is documented on DMG0, DMG and MGB, the
+		// others depend on how long the boot ROM ran.
+		self.timer.set_divider(match model {
+			Model::Dmg0 => 0x182C,
+			Model::Dmg | Model::Mgb => 0xABCC,
+			Model::Sgb | Model::Cgb => 0x0000,
+		});
+
+		// The APU must be powered on before its registers are written
+		self.write_u8(0xFF26, 0x80);
+
+		// The LCD is already on, so turning it on again mustn't hide
+		// the first frame.
+		self.ppu.skip_boot();
+
+		// NRx4 are written without the trigger bit, as the channels
+		// are left silent.
+		let registers: &[(u16, u8)] = &[
+			(0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E),
+			(0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8),
+			(0xFF0F, 0xE1),
+			(0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0x3F),
+			(0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0x3F),
+			(0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0x3F),
+			(0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0x3F),
+			(0xFF24, 0x77), (0xFF25, 0xF3),
+			(0xFF40, 0x91), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF45, 0x00),
+			(0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00),
+			(0xFFFF, 0x00),
+		];
+
+		for &(addr, value) in registers {
+			self.write_u8(addr, value);
+		}
+
+		// Channel 1 is still on after the boot sound, except on SGB.
+		let nr52 = if model == Model::Sgb { 0xF0 } else { 0xF1 };
+		self.apu.set_channel_status(nr52 & 0x0F);
+
+		self.bios_enable = false;
+	}
+
+	pub fn load_bios<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+		let mut bios = vec![];
+		reader.read_to_end(&mut bios)?;
+
+		self.load_bios_bytes(&bios)
+	}
+
+	pub fn load_bios_bytes(&mut self, bios: &[u8]) -> io::Result<()> {
+		if bios.len() != self.bios.len() {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("the boot ROM must be {} bytes long, not {}", self.bios.len(), bios.len())
+			));
 		}
+
+		self.bios.copy_from_slice(bios);
+		Ok(())
+	}
+
+	pub fn load_rom<R: Read>(&mut self, reader: R) -> Result<(), CartridgeError> {
+		self.cart = Cartridge::from_reader(reader)?;
+		Ok(())
 	}
 
-	pub fn load_bios(&mut self, mut file: std::fs::File) {
-		file.read_exact(&mut self.bios).unwrap();
+	pub fn load_rom_vec(&mut self, rom: Vec<u8>) -> Result<(), CartridgeError> {
+		self.cart = Cartridge::from_vec(rom)?;
+		Ok(())
 	}
 
-	pub fn load_rom(&mut self, file: std::fs::File) {
-		self.cart.load_file(file);
+	pub fn cartridge_header(&self) -> Option<&CartridgeHeader> { self.cart.header() }
+
+	pub fn has_battery(&self) -> bool { self.cart.has_battery() }
+	pub fn save_data(&self) -> Vec<u8> { self.cart.save_data() }
+	pub fn load_save_data(&mut self, data: &[u8]) { self.cart.load_save_data(data); }
+
+	pub fn set_rtc_clock(&mut self, clock: Box<dyn Clock>) {
+		self.cart.set_clock(clock);
 	}
 
 	pub fn delay(&mut self, cycles: u32) {
@@ -62,20 +199,39 @@ impl Bus {
 	}
 
 	pub fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x8000..=0x9FFF if !self.ppu.vram_accessible() => 0xFF,
+			0xFE00..=0xFE9F if !self.ppu.oam_accessible() => 0xFF,
+			_ => self.read_u8_unrestricted(addr),
+		}
+	}
+
+	// Reads without the restrictions the PPU puts on the CPU, as done by DMA
+	fn read_u8_unrestricted(&mut self, addr: u16) -> u8 {
 		match addr {
 			0..=0xFF if self.bios_enable => self.bios[addr as usize],
 			0..=0x7FFF => self.cart.read_rom_u8(addr),
+			0x8000..=0x9FFF => self.ppu.read_vram_u8(addr),
+			0xA000..=0xBFFF => self.cart.read_ram_u8(addr),
 			0xC000..=0xDFFF => self.wram[(addr & 0x1FFF) as usize],
 			0xE000..=0xFDFF => self.wram[(addr & 0x1FFF) as usize],
 			0xFE00..=0xFE9F => self.ppu.read_oam_u8(addr),
 			0xFEA0..=0xFEFF => 0xFF,
 			0xFF00 => self.joypad.read(),
+			0xFF01..=0xFF02 => self.serial.read_io_register(addr),
+			0xFF04..=0xFF07 => self.timer.read_io_register(addr),
+			0xFF0F => 0xE0 | self.int_flag,
+			0xFF10..=0xFF3F => self.apu.read_io_register(addr),
+			0xFF46 => self.dma_register,
 			0xFF40..=0xFF4B => self.ppu.read_io_register(addr),
-			0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
-			0xFFFF => {
-				(self.enable_vblank_irq as u8) |
-				((self.enable_stat_irq as u8) << 1)
+			0xFF4D if self.cgb => {
+				0x7E |
+				((self.double_speed as u8) << 7) |
+				(self.speed_switch_armed as u8)
 			}
+			0xFF4C..=0xFF7F => 0xFF,
+			0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
+			0xFFFF => self.int_enable,
 			_ => unimplemented!("This address has not been implemented yet. {:04x}", addr)
 		}
 	}
@@ -83,49 +239,87 @@ impl Bus {
 	pub fn write_u8(&mut self, addr: u16, value: u8) {
 		match addr {
 			0x0000..=0x7FFF => self.cart.write_rom_u8(addr, value),
+			0x8000..=0x9FFF if !self.ppu.vram_accessible() => { },
 			0x8000..=0x9FFF => self.ppu.write_vram_u8(addr, value),
 			0xA000..=0xBFFF => self.cart.write_ram_u8(addr, value),
 			0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize] = value,
+			0xFE00..=0xFE9F if !self.ppu.oam_accessible() => { },
 			0xFE00..=0xFE9F => self.ppu.write_oam_u8(addr, value),
 			0xFEA0..=0xFEFF => { },
 			0xFF00 => self.joypad.write(value),
-			0xFF01 => {},
-			0xFF02 => {},
-			// TODO: Timer registers
-			0xFF04..=0xFF07 => { },
-			0xFF0F => {
-				self.ppu.set_vblank_irq((value & 0x01) != 0);
-				self.ppu.set_stat_irq((value & 0x02) != 0);
-			}
-			0xFF10..=0xFF26 => self.apu.write_io_register(addr, value),
+			0xFF01..=0xFF02 => self.serial.write_io_register(addr, value),
+			0xFF04..=0xFF07 => self.timer.write_io_register(addr, value),
+			0xFF0F => self.int_flag = value & 0x1F,
+			0xFF10..=0xFF3F => self.apu.write_io_register(addr, value),
 			0xFF40..=0xFF45 => self.ppu.write_io_register(addr, value),
 			0xFF46 => {
 				// DMA
+				self.dma_register = value;
 				self.dma_ongoing = true;
 				self.dma_src = (value as u16) << 8;
 				self.dma_dst = 0xFE00;
 			}
 			0xFF47..=0xFF4B => self.ppu.write_io_register(addr, value),
+			0xFF4D if self.cgb => self.speed_switch_armed = (value & 0x01) != 0,
 			0xFF50 => self.bios_enable = false,
-			0xFF7F => {},
+			// CGB registers (VRAM and WRAM banks, palettes...), which
+			// aren't emulated yet
+			0xFF4C..=0xFF7F => {},
 			0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = value,
-			0xFFFF => {
-				self.enable_vblank_irq = (value & 0x01) != 0;
-				self.enable_stat_irq = (value & 0x02) != 0;
-			}
+			0xFFFF => self.int_enable = value,
 			_ => unimplemented!("Write to unmapped adress ({:04x}, {:02x})", addr, value),
 		}
 	}
 
 	pub fn spend(&mut self) {
-		let t_state = self.cycles_to_spend << 2;
+		// In double speed mode, a CPU cycle only lasts 2 dots.
+		let t_state = if self.double_speed {
+			self.cycles_to_spend << 1
+		} else {
+			self.cycles_to_spend << 2
+		};
 
 		self.ppu.spend(t_state);
+		self.serial.spend(t_state);
+
+		// The timer is clocked by the CPU, whatever its speed.
+		self.timer.spend(self.cycles_to_spend << 2);
+
+		// The frame sequencer is clocked by bit 4 of DIV, or bit 5 in
+		// double speed mode.
+		let div_bit = if self.double_speed { 13 } else { 12 };
+		self.apu.spend(t_state, (self.timer.divider() >> div_bit) & 1 != 0);
+
+		// Interruptions
+		if self.ppu.has_vblank_irq() {
+			self.ppu.ack_vblank_irq();
+			self.request_irq(Interrupt::VBlank);
+		}
+
+		if self.ppu.has_stat_irq() {
+			self.ppu.ack_stat_irq();
+			self.request_irq(Interrupt::Stat);
+		}
+
+		if self.timer.has_irq() {
+			self.timer.ack_irq();
+			self.request_irq(Interrupt::Timer);
+		}
+
+		if self.serial.has_irq() {
+			self.serial.ack_irq();
+			self.request_irq(Interrupt::Serial);
+		}
+
+		if self.joypad.has_irq() {
+			self.joypad.ack_irq();
+			self.request_irq(Interrupt::Joypad);
+		}
 
 		// DMA
 		if self.dma_ongoing {
 			for _ in 0..t_state {
-				let value = self.read_u8(self.dma_src);
+				let value = self.read_u8_unrestricted(self.dma_src);
 				self.ppu.write_oam_u8(self.dma_dst, value);
 
 				self.dma_src += 1;
@@ -149,6 +343,8 @@ impl Default for Bus {
 			ppu: Default::default(),
 			apu: Default::default(),
 			joypad: Default::default(),
+			serial: Default::default(),
+			timer: Default::default(),
 			bios: [0; 0x100],
 			hram: [0; 0x80],
 			wram: [0; 0x2000],
@@ -156,10 +352,16 @@ impl Default for Bus {
 			cycles_to_spend: 0,
 			bios_enable: true,
 
-			enable_vblank_irq: false,
-			enable_stat_irq: false,
+			cgb: false,
+
+			double_speed: false,
+			speed_switch_armed: false,
+
+			int_flag: 0,
+			int_enable: 0,
 
 			// DMA
+			dma_register: 0xFF,
 			dma_ongoing: false,
 			dma_src: 0,
 			dma_dst: 0xFE00,
@@ -0,0 +1,141 @@
+use super::CartridgeError;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    None,
+    Compatible,
+    Only,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// The cartridge header, found at 0x100-0x14F in the ROM.
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    pub title: String,
+    /// Only present on some later cartridges.
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub old_licensee_code: u8,
+    /// Only used when the old licensee code is 0x33.
+    pub new_licensee_code: Option<String>,
+    pub cartridge_type: u8,
+    /// ROM size in bytes.
+    pub rom_size: usize,
+    /// External RAM size in bytes, not counting RAM built into the MBC.
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, CartridgeError> {
+        if rom.len() < 0x150 {
+            return Err(CartridgeError::Truncated { len: rom.len() });
+        }
+
+        let cgb_support = match rom[0x143] {
+            0xC0 => CgbSupport::Only,
+            flag if (flag & 0x80) != 0 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+
+        // On CGB-era cartridges, the end of the title area is used
+        // by the manufacturer code and the CGB flag.
+        let manufacturer = &rom[0x13F..0x143];
+        let manufacturer_code = if cgb_support != CgbSupport::None &&
+            manufacturer.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            Some(String::from_utf8_lossy(manufacturer).into_owned())
+        } else {
+            None
+        };
+
+        let title_end = match (cgb_support, &manufacturer_code) {
+            (_, Some(_)) => 0x13F,
+            (CgbSupport::None, None) => 0x144,
+            (_, None) => 0x143,
+        };
+        let title = rom[0x134..title_end].iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect();
+
+        let old_licensee_code = rom[0x14B];
+        let new_licensee_code = if old_licensee_code == 0x33 {
+            Some(String::from_utf8_lossy(&rom[0x144..0x146]).into_owned())
+        } else {
+            None
+        };
+
+        let rom_size = match rom[0x148] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(CartridgeError::InvalidRomSize(code)),
+        };
+
+        let ram_size = match rom[0x149] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartridgeError::InvalidRamSize(code)),
+        };
+
+        let computed_header_checksum = rom[0x134..=0x14C].iter()
+            .fold(0u8, |checksum, &byte| checksum.wrapping_sub(byte).wrapping_sub(1));
+
+        // The global checksum covers the whole ROM except itself.
+        let computed_global_checksum = rom.iter()
+            .enumerate()
+            .filter(|&(addr, _)| addr != 0x14E && addr != 0x14F)
+            .fold(0u16, |checksum, (_, &byte)| checksum.wrapping_add(byte as u16));
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+            cgb_support,
+            sgb_support: rom[0x146] == 0x03,
+            old_licensee_code,
+            new_licensee_code,
+            cartridge_type: rom[0x147],
+            rom_size,
+            ram_size,
+            destination: if rom[0x14A] == 0x00 { Destination::Japan } else { Destination::Overseas },
+            version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: ((rom[0x14E] as u16) << 8) | rom[0x14F] as u16,
+
+            computed_header_checksum,
+            computed_global_checksum,
+        })
+    }
+
+    /// The boot ROM refuses to start games with an invalid header checksum.
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    /// Nothing checks the global checksum on hardware, but a mismatch
+    /// usually means a bad dump.
+    pub fn is_global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        )
+    }
+}
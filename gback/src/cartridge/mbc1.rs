@@ -0,0 +1,107 @@
+use super::{Mbc, read_or_open_bus};
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+
+    ram_enable: bool,
+    bank1: u8,
+    bank2: u8,
+    advanced_mode: bool,
+
+    // MBC1M multicarts only wire 4 bits of BANK1
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Mbc1 {
+        let multicart = Self::is_multicart(&rom);
+
+        Mbc1 {
+            rom,
+            ram,
+            ram_enable: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_mode: false,
+            multicart,
+        }
+    }
+
+    // Multicarts are 1 MiB, and have another game with its own
+    // Nintendo logo starting at bank 0x10.
+    fn is_multicart(rom: &[u8]) -> bool {
+        rom.len() == 0x100000 && rom[0x104..0x134] == rom[0x40104..0x40134]
+    }
+
+    fn bank1_bits(&self) -> u8 {
+        if self.multicart { 4 } else { 5 }
+    }
+
+    fn rom_offset(&self, bank: usize, addr: u16) -> usize {
+        let bank = bank % (self.rom.len() / 0x4000).max(1);
+
+        (bank << 14) | (addr & 0x3FFF) as usize
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        let bank = if self.advanced_mode { self.bank2 as usize } else { 0 };
+
+        ((bank << 13) | (addr & 0x1FFF) as usize) % self.ram.len()
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom_u8(&self, addr: u16) -> u8 {
+        let bank2 = (self.bank2 as usize) << self.bank1_bits();
+
+        let bank = match addr {
+            0x0000..=0x3FFF if self.advanced_mode => bank2,
+            0x0000..=0x3FFF => 0,
+            _ => {
+                let mask = (1 << self.bank1_bits()) - 1;
+                bank2 | (self.bank1 & mask) as usize
+            }
+        };
+
+        read_or_open_bus(&self.rom, self.rom_offset(bank, addr))
+    }
+
+    fn write_rom_u8(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = (value & 0x0F) == 0x0A,
+            0x2000..=0x3FFF => {
+                // Writing 0 selects bank 1, and the check is done on all 5 bits,
+                // even on multicarts.
+                self.bank1 = value & 0x1F;
+
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            },
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            0x6000..=0x7FFF => self.advanced_mode = (value & 0x01) != 0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_ram_u8(&self, addr: u16) -> u8 {
+        if !self.ram_enable || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[self.ram_offset(addr)]
+    }
+
+    fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        if !self.ram_enable || self.ram.is_empty() {
+            return;
+        }
+
+        let offset = self.ram_offset(addr);
+        self.ram[offset] = value;
+    }
+
+    fn ram(&self) -> &[u8] { &self.ram }
+    fn ram_mut(&mut self) -> &mut [u8] { &mut self.ram }
+}
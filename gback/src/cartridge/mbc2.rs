@@ -0,0 +1,68 @@
+use super::{Mbc, read_or_open_bus};
+
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    // 512 half-bytes of RAM are built into the MBC itself
+    ram: [u8; 0x200],
+
+    ram_enable: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Mbc2 {
+        Mbc2 {
+            rom,
+            ram: [0; 0x200],
+            ram_enable: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom_u8(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        };
+
+        read_or_open_bus(&self.rom, (bank << 14) | (addr & 0x3FFF) as usize)
+    }
+
+    fn write_rom_u8(&mut self, addr: u16, value: u8) {
+        // Bit 8 of the address selects the register.
+        match addr {
+            0x0000..=0x3FFF if (addr & 0x100) == 0 => {
+                self.ram_enable = (value & 0x0F) == 0x0A;
+            },
+            0x0000..=0x3FFF => {
+                self.rom_bank = value & 0x0F;
+
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            },
+            _ => { },
+        }
+    }
+
+    // Only the lower 9 bits of the address are used, so the RAM
+    // is echoed all over 0xA000-0xBFFF.
+    fn read_ram_u8(&self, addr: u16) -> u8 {
+        if !self.ram_enable {
+            return 0xFF;
+        }
+
+        0xF0 | self.ram[(addr & 0x1FF) as usize]
+    }
+
+    fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        if self.ram_enable {
+            self.ram[(addr & 0x1FF) as usize] = value & 0x0F;
+        }
+    }
+
+    fn ram(&self) -> &[u8] { &self.ram }
+    fn ram_mut(&mut self) -> &mut [u8] { &mut self.ram }
+}
@@ -0,0 +1,429 @@
+use super::{Mbc, Clock, SystemClock, read_or_open_bus};
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+
+    ram_enable: bool,
+    rom_bank: u8,
+    // 0x00-0x07 selects a RAM bank, 0x08-0x0C an RTC register
+    ram_bank: u8,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, has_rtc: bool) -> Mbc3 {
+        Mbc3 {
+            rom,
+            ram,
+            rtc: if has_rtc { Some(Rtc::new(Box::new(SystemClock))) } else { None },
+            ram_enable: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        (((self.ram_bank as usize) << 13) | (addr & 0x1FFF) as usize) % self.ram.len()
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom_u8(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        };
+
+        read_or_open_bus(&self.rom, (bank << 14) | (addr & 0x3FFF) as usize)
+    }
+
+    fn write_rom_u8(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = (value & 0x0F) == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            },
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_ram_u8(&self, addr: u16) -> u8 {
+        if !self.ram_enable {
+            return 0xFF;
+        }
+
+        match (self.ram_bank, &self.rtc) {
+            (0x00..=0x07, _) if !self.ram.is_empty() => self.ram[self.ram_offset(addr)],
+            (0x08..=0x0C, Some(rtc)) => rtc.read_register(self.ram_bank),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        if !self.ram_enable {
+            return;
+        }
+
+        match (self.ram_bank, &mut self.rtc) {
+            (0x00..=0x07, _) if !self.ram.is_empty() => {
+                let offset = self.ram_offset(addr);
+                self.ram[offset] = value;
+            },
+            (0x08..=0x0C, Some(rtc)) => rtc.write_register(self.ram_bank, value),
+            _ => {},
+        }
+    }
+
+    fn ram(&self) -> &[u8] { &self.ram }
+    fn ram_mut(&mut self) -> &mut [u8] { &mut self.ram }
+
+    // The RTC state goes in a footer after the RAM, the way
+    // VBA-M and BGB store it.
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.save_footer());
+        }
+
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = self.ram.len().min(data.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_footer(&data[len..]);
+        }
+    }
+
+    fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_clock(clock);
+        }
+    }
+}
+
+struct Rtc {
+    clock: Box<dyn Clock>,
+    // Time at which the registers were last brought up to date
+    last_update: u64,
+
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    carry: bool,
+
+    latched: [u8; 5],
+    // Latching happens when writing 0x00 then 0x01
+    latch_armed: bool,
+}
+
+impl Rtc {
+    fn new(clock: Box<dyn Clock>) -> Rtc {
+        let last_update = clock.now();
+
+        Rtc {
+            clock,
+            last_update,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halt: false,
+            carry: false,
+            latched: [0; 5],
+            latch_armed: false,
+        }
+    }
+
+    fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.update();
+        self.last_update = clock.now();
+        self.clock = clock;
+    }
+
+    // Advances the registers by the time elapsed since the last update.
+    fn update(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last_update);
+        self.last_update = now;
+
+        if self.halt || elapsed == 0 {
+            return;
+        }
+
+        let mut total = elapsed +
+            self.seconds as u64 +
+            self.minutes as u64 * 60 +
+            self.hours as u64 * 3600 +
+            self.days as u64 * 86400;
+
+        self.seconds = (total % 60) as u8;
+        total /= 60;
+        self.minutes = (total % 60) as u8;
+        total /= 60;
+        self.hours = (total % 24) as u8;
+        total /= 24;
+
+        if total > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (total & 0x1FF) as u16;
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            ((self.days >> 8) as u8 & 0x01) |
+            (self.halt as u8) << 6 |
+            (self.carry as u8) << 7,
+        ]
+    }
+
+    // Current and latched registers as 32-bit words, then the
+    // time they were last updated as a 64-bit UNIX timestamp.
+    fn save_footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(48);
+        for &register in self.registers().iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(register as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&self.last_update.to_le_bytes());
+
+        footer
+    }
+
+    // Some emulators only write a 32-bit timestamp, making the footer 44 bytes long.
+    fn load_footer(&mut self, footer: &[u8]) {
+        if footer.len() < 44 {
+            return;
+        }
+
+        let word = |index: usize| footer[index * 4];
+        let timestamp = if footer.len() >= 48 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&footer[40..48]);
+            u64::from_le_bytes(bytes)
+        } else {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&footer[40..44]);
+            u32::from_le_bytes(bytes) as u64
+        };
+
+        self.seconds = word(0) & 0x3F;
+        self.minutes = word(1) & 0x3F;
+        self.hours = word(2) & 0x1F;
+        self.days = word(3) as u16 | ((word(4) as u16 & 0x01) << 8);
+        self.halt = (word(4) & 0x40) != 0;
+        self.carry = (word(4) & 0x80) != 0;
+
+        for (index, latched) in self.latched.iter_mut().enumerate() {
+            *latched = word(5 + index);
+        }
+
+        // The time elapsed since the save is accounted for on the next update.
+        self.last_update = timestamp;
+    }
+
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update();
+            self.latched = self.registers();
+        }
+
+        self.latch_armed = value == 0x00;
+    }
+
+    fn read_register(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) {
+        self.update();
+
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.halt = (value & 0x40) != 0;
+                self.carry = (value & 0x80) != 0;
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use super::*;
+
+    // Clock whose time is only moved by the test
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    const START: u64 = 1_600_000_000;
+
+    fn cartridge() -> (Mbc3, Rc<Cell<u64>>) {
+        let time = Rc::new(Cell::new(START));
+        let mut mbc = Mbc3::new(vec![0; 0x8000], vec![0; 0x2000], true);
+        mbc.set_clock(Box::new(FakeClock(time.clone())));
+        mbc.write_rom_u8(0x0000, 0x0A);
+
+        (mbc, time)
+    }
+
+    fn write_rtc(mbc: &mut Mbc3, register: u8, value: u8) {
+        mbc.write_rom_u8(0x4000, register);
+        mbc.write_ram_u8(0xA000, value);
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.write_rom_u8(0x6000, 0x00);
+        mbc.write_rom_u8(0x6000, 0x01);
+    }
+
+    // Seconds, minutes, hours, low and high day registers
+    fn read_rtc(mbc: &mut Mbc3) -> [u8; 5] {
+        let mut registers = [0; 5];
+        for (index, register) in registers.iter_mut().enumerate() {
+            mbc.write_rom_u8(0x4000, 0x08 + index as u8);
+            *register = mbc.read_ram_u8(0xA000);
+        }
+
+        registers
+    }
+
+    #[test]
+    fn registers_roll_over() {
+        let (mut mbc, time) = cartridge();
+        write_rtc(&mut mbc, 0x08, 59);
+        write_rtc(&mut mbc, 0x09, 59);
+        write_rtc(&mut mbc, 0x0A, 23);
+
+        time.set(START + 1);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 1, 0]);
+
+        time.set(START + 1 + 86400 + 3600 + 60 + 1);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [1, 1, 1, 2, 0]);
+    }
+
+    #[test]
+    fn day_counter_sets_carry() {
+        let (mut mbc, time) = cartridge();
+        write_rtc(&mut mbc, 0x08, 59);
+        write_rtc(&mut mbc, 0x09, 59);
+        write_rtc(&mut mbc, 0x0A, 23);
+        write_rtc(&mut mbc, 0x0B, 0xFF);
+        write_rtc(&mut mbc, 0x0C, 0x01);
+
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [59, 59, 23, 0xFF, 0x01]);
+
+        time.set(START + 1);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 0, 0x80]);
+
+        // The carry stays set until cleared by the game.
+        time.set(START + 86401);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 1, 0x80]);
+
+        write_rtc(&mut mbc, 0x0C, 0x00);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 1, 0x00]);
+    }
+
+    #[test]
+    fn halt_stops_time() {
+        let (mut mbc, time) = cartridge();
+        write_rtc(&mut mbc, 0x08, 10);
+        write_rtc(&mut mbc, 0x0C, 0x40);
+
+        time.set(START + 1000);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [10, 0, 0, 0, 0x40]);
+
+        // Time spent halted is lost.
+        write_rtc(&mut mbc, 0x0C, 0x00);
+        time.set(START + 1005);
+        latch(&mut mbc);
+        assert_eq!(read_rtc(&mut mbc), [15, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn registers_only_change_when_latched() {
+        let (mut mbc, time) = cartridge();
+        latch(&mut mbc);
+
+        time.set(START + 5);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 0, 0]);
+
+        // Writing 0x01 without 0x00 before it doesn't latch.
+        mbc.write_rom_u8(0x6000, 0x01);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 0, 0]);
+
+        mbc.write_rom_u8(0x6000, 0x00);
+        assert_eq!(read_rtc(&mut mbc), [0, 0, 0, 0, 0]);
+        mbc.write_rom_u8(0x6000, 0x01);
+        assert_eq!(read_rtc(&mut mbc), [5, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let (mut mbc, time) = cartridge();
+        mbc.write_ram_u8(0xA123, 0x42);
+        write_rtc(&mut mbc, 0x08, 30);
+        write_rtc(&mut mbc, 0x09, 20);
+        write_rtc(&mut mbc, 0x0A, 10);
+        latch(&mut mbc);
+
+        let data = mbc.save_data();
+        assert_eq!(data.len(), 0x2000 + 48);
+
+        // The time elapsed while the game was off is counted on load,
+        // with both footer lengths.
+        for &footer_len in [48, 44].iter() {
+            time.set(START + 60);
+            let (mut loaded, _) = cartridge();
+            loaded.set_clock(Box::new(FakeClock(time.clone())));
+            loaded.load_save_data(&data[..0x2000 + footer_len]);
+
+            loaded.write_rom_u8(0x4000, 0x00);
+            assert_eq!(loaded.read_ram_u8(0xA123), 0x42);
+            assert_eq!(read_rtc(&mut loaded), [30, 20, 10, 0, 0]);
+
+            latch(&mut loaded);
+            assert_eq!(read_rtc(&mut loaded), [30, 21, 10, 0, 0]);
+            time.set(START);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use super::{Mbc, read_or_open_bus};
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+
+    ram_enable: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+
+    // On rumble carts, bit 3 of the RAM bank register drives the motor
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            rom,
+            ram,
+            ram_enable: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        (((self.ram_bank as usize) << 13) | (addr & 0x1FFF) as usize) % self.ram.len()
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom_u8(&self, addr: u16) -> u8 {
+        // Unlike older MBCs, bank 0 can be mapped at 0x4000.
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        };
+
+        read_or_open_bus(&self.rom, (bank << 14) | (addr & 0x3FFF) as usize)
+    }
+
+    fn write_rom_u8(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8),
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.ram_bank = value & 0x07;
+                    self.rumble = (value & 0x08) != 0;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            },
+            0x6000..=0x7FFF => { },
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_ram_u8(&self, addr: u16) -> u8 {
+        if !self.ram_enable || self.ram.is_empty() {
+            return 0xFF;
+        }
+
+        self.ram[self.ram_offset(addr)]
+    }
+
+    fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        if !self.ram_enable || self.ram.is_empty() {
+            return;
+        }
+
+        let offset = self.ram_offset(addr);
+        self.ram[offset] = value;
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn ram(&self) -> &[u8] { &self.ram }
+    fn ram_mut(&mut self) -> &mut [u8] { &mut self.ram }
+}
@@ -0,0 +1,242 @@
+mod header;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+
+use std::io::Read;
+use std::time::{SystemTime, UNIX_EPOCH};
+pub use header::{CartridgeHeader, CgbSupport, Destination};
+use mbc1::Mbc1;
+use mbc2::Mbc2;
+use mbc3::Mbc3;
+use mbc5::Mbc5;
+
+pub trait Mbc {
+    fn read_rom_u8(&self, addr: u16) -> u8;
+    fn write_rom_u8(&mut self, addr: u16, value: u8);
+    fn read_ram_u8(&self, addr: u16) -> u8;
+    fn write_ram_u8(&mut self, addr: u16, value: u8);
+
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    // Battery-backed data, in the common .sav layout
+    fn save_data(&self) -> Vec<u8> {
+        self.ram().to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram = self.ram_mut();
+        let len = ram.len().min(data.len());
+
+        ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    // Only used by mappers with a real-time clock
+    fn set_clock(&mut self, _clock: Box<dyn Clock>) { }
+
+    // Only used by mappers with a rumble motor
+    fn rumble(&self) -> bool { false }
+}
+
+/// Time source for cartridges with a real-time clock.
+pub trait Clock {
+    /// Current UNIX time, in seconds.
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(std::io::Error),
+    /// The file is too short to even hold a header.
+    Truncated { len: usize },
+    /// The file is shorter than the ROM size in the header, or isn't
+    /// made of whole 16 KiB banks.
+    SizeMismatch { expected: usize, actual: usize },
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    UnsupportedMapper(u8),
+}
+
+impl std::fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CartridgeError::Io(e) => write!(f, "couldn't read the ROM: {}", e),
+            CartridgeError::Truncated { len } => {
+                write!(f, "the ROM is truncated ({} bytes)", len)
+            },
+            CartridgeError::SizeMismatch { expected, actual } => write!(
+                f,
+                "the header announces {} bytes of ROM, but the file is {} bytes long",
+                expected,
+                actual
+            ),
+            CartridgeError::InvalidRomSize(code) => write!(f, "invalid ROM size ({:02x})", code),
+            CartridgeError::InvalidRamSize(code) => write!(f, "invalid RAM size ({:02x})", code),
+            CartridgeError::UnsupportedMapper(cart_type) => {
+                write!(f, "unsupported cartridge type ({:02x})", cart_type)
+            },
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CartridgeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CartridgeError {
+    fn from(e: std::io::Error) -> CartridgeError {
+        CartridgeError::Io(e)
+    }
+}
+
+pub struct Cartridge {
+    mbc: Box<dyn Mbc>,
+    header: Option<CartridgeHeader>,
+}
+
+impl Cartridge {
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Cartridge, CartridgeError> {
+        let mut rom = vec![];
+        reader.read_to_end(&mut rom)?;
+
+        Self::from_vec(rom)
+    }
+
+    pub fn from_bytes(rom: &[u8]) -> Result<Cartridge, CartridgeError> {
+        Self::from_vec(rom.to_vec())
+    }
+
+    pub fn from_vec(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = CartridgeHeader::parse(&rom)?;
+
+        // Overdumped or padded images are larger than they should be,
+        // which is harmless.
+        if rom.len() < header.rom_size || !rom.len().is_multiple_of(0x4000) {
+            return Err(CartridgeError::SizeMismatch {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+
+        Ok(Cartridge {
+            mbc: Self::mbc_from_rom(&header, rom)?,
+            header: Some(header),
+        })
+    }
+
+    fn mbc_from_rom(header: &CartridgeHeader, rom: Vec<u8>) -> Result<Box<dyn Mbc>, CartridgeError> {
+        let ram = vec![0; header.ram_size];
+
+        let mbc: Box<dyn Mbc> = match header.cartridge_type {
+            0x00 | 0x08 | 0x09 => Box::new(RomOnly { rom, ram }),
+            0x01..=0x03 => Box::new(Mbc1::new(rom, ram)),
+            0x05 | 0x06 => Box::new(Mbc2::new(rom)),
+            0x0F | 0x10 => Box::new(Mbc3::new(rom, ram, true)),
+            0x11..=0x13 => Box::new(Mbc3::new(rom, ram, false)),
+            0x19..=0x1B => Box::new(Mbc5::new(rom, ram, false)),
+            0x1C..=0x1E => Box::new(Mbc5::new(rom, ram, true)),
+            cart_type => return Err(CartridgeError::UnsupportedMapper(cart_type)),
+        };
+
+        Ok(mbc)
+    }
+
+    /// The header of the loaded ROM, if any.
+    pub fn header(&self) -> Option<&CartridgeHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.header.as_ref().is_some_and(CartridgeHeader::has_battery)
+    }
+
+    pub fn save_data(&self) -> Vec<u8> {
+        self.mbc.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mbc.load_save_data(data);
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.mbc.set_clock(clock);
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.mbc.rumble()
+    }
+
+    pub fn read_rom_u8(&self, addr: u16) -> u8 {
+        self.mbc.read_rom_u8(addr)
+    }
+
+    pub fn write_rom_u8(&mut self, addr: u16, value: u8) {
+        self.mbc.write_rom_u8(addr, value);
+    }
+
+    pub fn read_ram_u8(&self, addr: u16) -> u8 {
+        self.mbc.read_ram_u8(addr)
+    }
+
+    pub fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        self.mbc.write_ram_u8(addr, value);
+    }
+}
+
+impl Default for Cartridge {
+    fn default() -> Cartridge {
+        Cartridge {
+            mbc: Box::new(RomOnly { rom: vec![], ram: vec![] }),
+            header: None,
+        }
+    }
+}
+
+// Reading outside of the ROM or RAM returns open bus.
+fn read_or_open_bus(data: &[u8], offset: usize) -> u8 {
+    data.get(offset).copied().unwrap_or(0xFF)
+}
+
+struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl Mbc for RomOnly {
+    fn read_rom_u8(&self, addr: u16) -> u8 {
+        read_or_open_bus(&self.rom, addr as usize)
+    }
+
+    fn write_rom_u8(&mut self, _addr: u16, _value: u8) { }
+
+    fn read_ram_u8(&self, addr: u16) -> u8 {
+        read_or_open_bus(&self.ram, (addr - 0xA000) as usize)
+    }
+
+    fn write_ram_u8(&mut self, addr: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut((addr - 0xA000) as usize) {
+            *byte = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] { &self.ram }
+    fn ram_mut(&mut self) -> &mut [u8] { &mut self.ram }
+}
@@ -1,4 +1,5 @@
 use crate::bus::Bus;
+use crate::Model;
 
 #[derive(Default)]
 pub struct CPU {
@@ -19,6 +20,14 @@ pub struct CPU {
 	carry: bool,
 
 	ime: bool,
+
+	// Low-power states
+	halted: bool,
+	halt_bug: bool,
+	stopped: bool,
+
+	// Set when an illegal opcode has been executed, with its opcode and address
+	lockup: Option<(u8, u16)>,
 }
 
 impl CPU {
@@ -210,6 +219,20 @@ impl CPU {
 		self.a = res as u8;
 	}
 
+	fn add_sp_i8(&mut self, bus: &mut Bus) -> u16 {
+		let value = self.next_u8(bus);
+		let sp = self.sp;
+		let v = value as u16;
+
+		// Flags are computed on the low byte, as an unsigned addition
+		self.zero = false;
+		self.sub = false;
+		self.halfcarry = (sp & 0x0f) + (v & 0x0f) > 0x0f;
+		self.carry = (sp & 0xff) + v > 0xff;
+
+		sp.wrapping_add(value as i8 as u16)
+	}
+
 	fn add_u16(&mut self, bus: &mut Bus, value: u16) {
 		let a = self.hl() as u32;
 		let v = value as u32;
@@ -424,9 +447,64 @@ impl CPU {
 		self.halfcarry = true;
 	}
 
+	// Misc functions
+	fn daa(&mut self) {
+		let mut a = self.a;
+
+		if !self.sub {
+			if self.carry || a > 0x99 {
+				a = a.wrapping_add(0x60);
+				self.carry = true;
+			}
+
+			if self.halfcarry || (a & 0x0f) > 0x09 {
+				a = a.wrapping_add(0x06);
+			}
+		} else {
+			if self.carry {
+				a = a.wrapping_sub(0x60);
+			}
+
+			if self.halfcarry {
+				a = a.wrapping_sub(0x06);
+			}
+		}
+
+		self.zero = a == 0;
+		self.halfcarry = false;
+		self.a = a;
+	}
+
+	fn halt(&mut self, bus: &mut Bus) {
+		// With IME off and an interrupt already pending, HALT exits
+		// immediately and the next opcode byte is read twice.
+		if !self.ime && bus.has_irq().is_some() {
+			self.halt_bug = true;
+		} else {
+			self.halted = true;
+		}
+	}
+
+	fn stop(&mut self, bus: &mut Bus) {
+		// STOP is two bytes long, the second one is ignored.
+		self.next_u8(bus);
+		bus.reset_divider();
+
+		if bus.speed_switch_requested() {
+			bus.switch_speed();
+		} else {
+			self.stopped = true;
+		}
+	}
+
 	fn run_instruction(&mut self, bus: &mut Bus) {
 		let instr = self.next_u8(bus);
 
+		if self.halt_bug {
+			self.pc = self.pc.wrapping_sub(1);
+			self.halt_bug = false;
+		}
+
 		match instr {
 			0x00 => {}
 			0x01 => { let bc = self.next_u16(bus); self.set_bc(bc); }
@@ -447,6 +525,7 @@ impl CPU {
 			0x0d => { self.c = self.dec_u8(self.c); }
 			0x0e => { self.c = self.next_u8(bus); }
 			0x0f => { self.a = self.rrc_u8(self.a); self.zero = false; }
+			0x10 => { self.stop(bus); }
 			0x11 => { let de = self.next_u16(bus); self.set_de(de); }
 			0x12 => { self.write_u8(bus, self.de(), self.a); }
 			0x13 => { let de = self.inc_u16(bus, self.de()); self.set_de(de); }
@@ -474,6 +553,7 @@ impl CPU {
 			0x24 => { self.h = self.inc_u8(self.h); }
 			0x25 => { self.h = self.dec_u8(self.h); }
 			0x26 => { self.h = self.next_u8(bus); }
+			0x27 => { self.daa(); }
 			0x28 => { let c = self.zero; self.jr_cond(bus, c); }
 			0x29 => { self.add_u16(bus, self.hl()); }
 			0x2a => {
@@ -527,7 +607,7 @@ impl CPU {
 				self.carry = !self.carry;
 			}
 			0x38 => { self.jr_cond(bus, self.carry); }
-			0x40 => { self.b = self.b; }
+			0x40 => {}
 			0x41 => { self.b = self.c; }
 			0x42 => { self.b = self.d; }
 			0x43 => { self.b = self.e; }
@@ -536,7 +616,7 @@ impl CPU {
 			0x46 => { self.b = self.read_hl(bus); }
 			0x47 => { self.b = self.a; }
 			0x48 => { self.c = self.b; }
-			0x49 => { self.c = self.c; }
+			0x49 => {}
 			0x4a => { self.c = self.d; }
 			0x4b => { self.c = self.e; }
 			0x4c => { self.c = self.h; }
@@ -545,7 +625,7 @@ impl CPU {
 			0x4f => { self.c = self.a; }
 			0x50 => { self.d = self.b; }
 			0x51 => { self.d = self.c; }
-			0x52 => { self.d = self.d; }
+			0x52 => {}
 			0x53 => { self.d = self.e; }
 			0x54 => { self.d = self.h; }
 			0x55 => { self.d = self.l; }
@@ -554,7 +634,7 @@ impl CPU {
 			0x58 => { self.e = self.b; }
 			0x59 => { self.e = self.c; }
 			0x5a => { self.e = self.d; }
-			0x5b => { self.e = self.e; }
+			0x5b => {}
 			0x5c => { self.e = self.h; }
 			0x5d => { self.e = self.l; }
 			0x5e => { self.e = self.read_hl(bus); }
@@ -563,7 +643,7 @@ impl CPU {
 			0x61 => { self.h = self.c; }
 			0x62 => { self.h = self.d; }
 			0x63 => { self.h = self.e; }
-			0x64 => { self.h = self.h; }
+			0x64 => {}
 			0x65 => { self.h = self.l; }
 			0x66 => { self.h = self.read_hl(bus); }
 			0x67 => { self.h = self.a; }
@@ -572,7 +652,7 @@ impl CPU {
 			0x6a => { self.l = self.d; }
 			0x6b => { self.l = self.e; }
 			0x6c => { self.l = self.h; }
-			0x6d => { self.l = self.l; }
+			0x6d => {}
 			0x6e => { self.l = self.read_hl(bus); }
 			0x6f => { self.l = self.a; }
 			0x70 => { self.write_hl(bus, self.b); }
@@ -581,6 +661,7 @@ impl CPU {
 			0x73 => { self.write_hl(bus, self.e); }
 			0x74 => { self.write_hl(bus, self.h); }
 			0x75 => { self.write_hl(bus, self.l); }
+			0x76 => { self.halt(bus); }
 			0x77 => { self.write_hl(bus, self.a); }
 			0x78 => { self.a = self.b; }
 			0x79 => { self.a = self.c; }
@@ -589,7 +670,7 @@ impl CPU {
 			0x7c => { self.a = self.h; }
 			0x7d => { self.a = self.l; }
 			0x7e => { self.a = self.read_hl(bus); }
-			0x7f => { self.a = self.a; }
+			0x7f => {}
 			0x80 => { self.add_u8(self.b); }
 			0x81 => { self.add_u8(self.c); }
 			0x82 => { self.add_u8(self.d); }
@@ -692,6 +773,10 @@ impl CPU {
 			0xe5 => { self.push(bus, self.hl()); }
 			0xe6 => { let value = self.next_u8(bus); self.and_u8(value); }
 			0xe7 => { self.rst(bus, 0x20); }
+			0xe8 => {
+				self.sp = self.add_sp_i8(bus);
+				bus.delay(2);
+			}
 			0xe9 => { self.set_pc(bus, self.hl()); }
 			0xea => { let addr = self.next_u16(bus); self.write_u8(bus, addr, self.a); }
 			0xee => { let value = self.next_u8(bus); self.xor_u8(value); }
@@ -701,19 +786,25 @@ impl CPU {
 				self.a = self.read_u8(bus, addr);
 			}
 			0xf1 => { let af = self.pop(bus); self.set_af(af); }
+			0xf2 => { self.a = self.read_u8(bus, 0xFF00 + (self.c as u16)); }
 			0xf3 => { self.ime = false; }
 			0xf5 => { self.push(bus, self.af()); }
+			0xf6 => { let value = self.next_u8(bus); self.or_u8(value); }
 			0xf7 => { self.rst(bus, 0x30); }
-			0xf8 => { 
-				let value = self.next_u8(bus) as i8 as i16;
-				self.set_hl((self.sp as i16 + value) as u16);
+			0xf8 => {
+				let value = self.add_sp_i8(bus);
+				self.set_hl(value);
+				bus.delay(1);
 			}
 			0xf9 => { bus.delay(1); self.sp = self.hl(); }
 			0xfa => { let addr = self.next_u16(bus); self.a = self.read_u8(bus, addr); }
 			0xfb => { self.ime = true; }
 			0xfe => { let value = self.next_u8(bus); self.cp_u8(value); }
 			0xff => { self.rst(bus, 0x38); }
-			_ => unimplemented!("Instruction not implemented ({:02x})", instr)
+			0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb |
+			0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+				self.lockup = Some((instr, self.pc.wrapping_sub(1)));
+			}
 		}
 	}
 
@@ -980,8 +1071,59 @@ impl CPU {
 		}
 	}
 
+	// Registers as the boot ROM leaves them. On DMG and MGB, H and C
+	// are only set if the header checksum isn't 0.
+	pub fn skip_boot(&mut self, model: Model, header_checksum: u8) {
+		let checksum_flags = if header_checksum != 0 { 0x30 } else { 0x00 };
+
+		let (af, bc, de, hl) = match model {
+			Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+			Model::Dmg => (0x0180 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+			Model::Mgb => (0xFF80 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+			Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+			Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+		};
+
+		self.set_af(af);
+		self.set_bc(bc);
+		self.set_de(de);
+		self.set_hl(hl);
+		self.sp = 0xFFFE;
+		self.pc = 0x0100;
+	}
+
+	pub fn lockup(&self) -> Option<(u8, u16)> { self.lockup }
+
 	pub fn step(&mut self, bus: &mut Bus) {
-		self.run_instruction(bus);
+		if self.lockup.is_some() {
+			// The CPU hangs forever, interrupts included.
+			bus.delay(1);
+			return;
+		}
+
+		if self.stopped {
+			// The bus keeps running so the host still gets frames,
+			// but nothing happens until a selected button is pressed.
+			bus.delay(1);
+
+			if !bus.joypad_pressed() {
+				return;
+			}
+
+			self.stopped = false;
+		}
+
+		if self.halted {
+			bus.delay(1);
+
+			if bus.has_irq().is_none() {
+				return;
+			}
+
+			self.halted = false;
+		} else {
+			self.run_instruction(bus);
+		}
 
 		if let Some(it) = bus.has_irq() {
 			if self.ime {
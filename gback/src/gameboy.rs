@@ -1,12 +1,17 @@
+use std::io::{self, Read};
 use crate::bus::Bus;
 use crate::cpu::CPU;
-use crate::{Platform, GBEvent};
+use crate::cartridge::{CartridgeError, CartridgeHeader, Clock};
+use crate::ppu::PPUAccuracy;
+use crate::apu::AudioConfig;
+use crate::{Platform, GBEvent, GBStatus, Button, Channel, Model};
 
 #[derive(Default, Debug)]
 pub struct Gameboy {
     cpu: CPU,
     bus: Bus,
     pub running: bool,
+    rumble: bool,
 }
 
 impl Gameboy {
@@ -14,27 +19,150 @@ impl Gameboy {
         Default::default()
     }
 
-    pub fn load_bios(&mut self, file: std::fs::File) {
-        self.bus.load_bios(file);
+    pub fn with_audio_config(config: AudioConfig) -> Gameboy {
+        Gameboy {
+            bus: Bus::with_audio_config(config),
+            ..Default::default()
+        }
+    }
+
+    pub fn load_bios<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        self.bus.load_bios(reader)
+    }
+
+    pub fn load_bios_bytes(&mut self, bios: &[u8]) -> io::Result<()> {
+        self.bus.load_bios_bytes(bios)
+    }
+
+    pub fn load_rom<R: Read>(&mut self, reader: R) -> Result<(), CartridgeError> {
+        self.bus.load_rom(reader)
+    }
+
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), CartridgeError> {
+        self.bus.load_rom_vec(rom.to_vec())
+    }
+
+    pub fn load_rom_vec(&mut self, rom: Vec<u8>) -> Result<(), CartridgeError> {
+        self.bus.load_rom_vec(rom)
+    }
+
+    /// Starts directly at 0x0100 with the state the boot ROM of `model`
+    /// would leave behind, instead of running a boot ROM.
+    /// Must be called after the ROM is loaded.
+    pub fn skip_boot(&mut self, model: Model) {
+        let header_checksum = self.bus.cartridge_header()
+            .map_or(0, |header| header.header_checksum);
+
+        self.cpu.skip_boot(model, header_checksum);
+        self.bus.skip_boot(model);
+    }
+
+    pub fn cartridge_header(&self) -> Option<&CartridgeHeader> {
+        self.bus.cartridge_header()
+    }
+
+    /// Returns the battery-backed RAM (and RTC state) in the common .sav layout,
+    /// or `None` if the cartridge has no battery.
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        if self.bus.has_battery() {
+            Some(self.bus.save_data())
+        } else {
+            None
+        }
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.bus.load_save_data(data);
+    }
+
+    /// Replaces the time source of the cartridge's real-time clock, if it has one.
+    /// Must be called after the ROM is loaded.
+    pub fn set_rtc_clock(&mut self, clock: Box<dyn Clock>) {
+        self.bus.set_rtc_clock(clock);
     }
 
-    pub fn load_rom(&mut self, file: std::fs::File) {
-        self.bus.load_rom(file);
+    pub fn set_ppu_accuracy(&mut self, accuracy: PPUAccuracy) {
+        self.bus.set_ppu_accuracy(accuracy);
     }
 
-    pub fn run_frame(&mut self, platform: &mut dyn Platform) {
+    /// When disabled, the CPU can access VRAM and OAM whatever the PPU
+    /// is doing, which can help when debugging.
+    pub fn set_access_restrictions(&mut self, enabled: bool) {
+        self.bus.set_access_restrictions(enabled);
+    }
+
+    /// Sets the rate of the samples sent to the platform, in Hz. It can be
+    /// nudged between frames to keep an audio queue from running dry.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.bus.set_sample_rate(rate);
+    }
+
+    pub fn is_channel_muted(&self, channel: Channel) -> bool {
+        self.bus.is_channel_muted(channel)
+    }
+
+    /// Leaves a channel out of the mixed output. Channel streams aren't affected.
+    pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) {
+        self.bus.set_channel_muted(channel, muted);
+    }
+
+    pub fn solo_channel(&self) -> Option<Channel> {
+        self.bus.solo_channel()
+    }
+
+    /// Only lets `channel` be heard in the mixed output, muted or not.
+    pub fn set_solo_channel(&mut self, channel: Option<Channel>) {
+        self.bus.set_solo_channel(channel);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.set_button(button, pressed);
+    }
+
+    pub fn status(&self) -> GBStatus {
+        match self.cpu.lockup() {
+            Some((opcode, addr)) => GBStatus::CpuLockup { opcode, addr },
+            None => GBStatus::Running,
+        }
+    }
+
+    pub fn run_frame(&mut self, platform: &mut dyn Platform) -> GBStatus {
         while !self.bus.is_frame_done() {
             self.cpu.step(&mut self.bus);
             self.bus.spend();
+
+            if self.bus.rumble() != self.rumble {
+                self.rumble = self.bus.rumble();
+                platform.set_rumble(self.rumble);
+            }
         }
 
         platform.present_buffer(&mut self.bus.frame_buffer());
         self.bus.ack_frame_done();
 
+        platform.queue_samples(self.bus.audio_samples());
+        for &channel in Channel::ALL.iter() {
+            if let Some(samples) = self.bus.channel_samples(channel) {
+                platform.queue_channel_samples(channel, samples);
+            }
+        }
+        self.bus.ack_audio_samples();
+
         while let Some(event) = platform.process_events() {
             match event {
                 GBEvent::Quit => self.running = false,
+                GBEvent::ButtonDown(button) => self.set_button(button, true),
+                GBEvent::ButtonUp(button) => self.set_button(button, false),
+                GBEvent::ToggleMute(channel) => {
+                    self.set_channel_muted(channel, !self.is_channel_muted(channel));
+                },
+                GBEvent::ToggleSolo(channel) => {
+                    let solo = if self.solo_channel() == Some(channel) { None } else { Some(channel) };
+                    self.set_solo_channel(solo);
+                },
             }
         }
+
+        self.status()
     }
 }
\ No newline at end of file
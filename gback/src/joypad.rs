@@ -1,6 +1,19 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+	Right,
+	Left,
+	Up,
+	Down,
+	A,
+	B,
+	Select,
+	Start,
+}
+
 #[derive(Default)]
 pub struct Joypad {
-	mode: bool,
+	select_directions: bool,
+	select_actions: bool,
 	right: bool,
 	left: bool,
 	up: bool,
@@ -9,26 +22,77 @@ pub struct Joypad {
 	b: bool,
 	start: bool,
 	select: bool,
+
+	// Interruptions
+	irq: bool,
 }
 
 impl Joypad {
-	pub fn read(&self) -> u8 {
-		if self.mode {
-			0xE0 |
-			((!self.down) as u8) << 3 |
-			((!self.up) as u8) << 2 |
-			((!self.left) as u8) << 1 |
-			(!self.right) as u8
-		} else {
-			0xD0 |
-			((!self.start) as u8) << 3 |
-			((!self.select) as u8) << 2 |
-			((!self.a) as u8) << 1 |
-			(!self.b) as u8		
+	pub fn has_irq(&self) -> bool { self.irq }
+	pub fn ack_irq(&mut self) { self.irq = false; }
+
+	// Lines are active low, and both groups are ANDed together
+	// when they are both selected.
+	fn lines(&self) -> u8 {
+		let mut lines = 0x0F;
+
+		if self.select_directions {
+			lines &= !(
+				(self.down as u8) << 3 |
+				(self.up as u8) << 2 |
+				(self.left as u8) << 1 |
+				(self.right as u8)
+			);
+		}
+
+		if self.select_actions {
+			lines &= !(
+				(self.start as u8) << 3 |
+				(self.select as u8) << 2 |
+				(self.a as u8) << 1 |
+				(self.b as u8)
+			);
 		}
+
+		lines
+	}
+
+	// The interrupt fires whenever a line goes from high to low.
+	fn update_lines<F: FnOnce(&mut Self)>(&mut self, f: F) {
+		let old_lines = self.lines();
+		f(self);
+
+		if (old_lines & !self.lines()) != 0 {
+			self.irq = true;
+		}
+	}
+
+	pub fn set_button(&mut self, button: Button, pressed: bool) {
+		self.update_lines(|joypad| {
+			match button {
+				Button::Right => joypad.right = pressed,
+				Button::Left => joypad.left = pressed,
+				Button::Up => joypad.up = pressed,
+				Button::Down => joypad.down = pressed,
+				Button::A => joypad.a = pressed,
+				Button::B => joypad.b = pressed,
+				Button::Select => joypad.select = pressed,
+				Button::Start => joypad.start = pressed,
+			}
+		});
+	}
+
+	pub fn read(&self) -> u8 {
+		0xC0 |
+		((!self.select_actions) as u8) << 5 |
+		((!self.select_directions) as u8) << 4 |
+		self.lines()
 	}
 
 	pub fn write(&mut self, value: u8) {
-		self.mode = (value & 0x20) != 0;
+		self.update_lines(|joypad| {
+			joypad.select_directions = (value & 0x10) == 0;
+			joypad.select_actions = (value & 0x20) == 0;
+		});
 	}
-}
\ No newline at end of file
+}
@@ -3,16 +3,48 @@ pub mod cartridge;
 pub mod cpu;
 pub mod bus;
 pub mod joypad;
+pub mod serial;
+pub mod timer;
 pub mod ppu;
 pub mod apu;
 
 pub use gameboy::Gameboy;
+pub use joypad::Button;
+pub use apu::Channel;
 
 pub enum GBEvent {
-	Quit
+	Quit,
+	ButtonDown(Button),
+	ButtonUp(Button),
+	ToggleMute(Channel),
+	ToggleSolo(Channel),
+}
+
+/// Hardware model, used for the post-boot state when running without a boot ROM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+	Dmg0,
+	Dmg,
+	Mgb,
+	Sgb,
+	Cgb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBStatus {
+	Running,
+	/// The CPU executed an illegal opcode and is hung until reset.
+	CpuLockup { opcode: u8, addr: u16 },
 }
 
 pub trait Platform {
 	fn present_buffer(&mut self, buffer: &mut [u8]);
 	fn process_events(&mut self) -> Option<GBEvent>;
+	fn set_rumble(&mut self, _enabled: bool) {}
+	/// Receives the audio of the last frame, as interleaved left and
+	/// right samples at the rate given to `Gameboy::set_sample_rate`.
+	fn queue_samples(&mut self, _samples: &[f32]) {}
+	/// Receives the audio of the last frame for a single channel, when
+	/// channel streams are enabled in the `AudioConfig`.
+	fn queue_channel_samples(&mut self, _channel: Channel, _samples: &[f32]) {}
 }
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use crate::ppu::PPU;
+use crate::ppu::render::Sprite;
+
+#[derive(Copy, Clone, Default)]
+struct ObjPixel {
+	color: u8,
+	palette: bool,
+	behind_bg: bool,
+}
+
+// State of the pixel FIFO renderer during mode 3
+#[derive(Default)]
+pub(super) struct Fifo {
+	// Next pixel to be pushed to the LCD
+	x: u8,
+	// Pixels thrown away at the start of the line, for SCX's fine scroll
+	discard: u8,
+	bg: VecDeque<u8>,
+	obj: VecDeque<ObjPixel>,
+	sprites: Vec<Sprite>,
+
+	// Background fetcher
+	fetch_dot: u8,
+	fetch_x: u8,
+	tile: u8,
+	data: (u8, u8),
+	window: bool,
+	window_drawn: bool,
+
+	// Dots left before the fetcher starts, or before a sprite is fetched
+	stall: u8,
+	pending_sprite: Option<Sprite>,
+}
+
+impl PPU {
+	pub(super) fn start_fifo_line(&mut self) {
+		if self.ly == self.wy {
+			self.wy_triggered = true;
+		}
+
+		self.fifo = Fifo {
+			discard: self.scx & 0x07,
+			sprites: if self.obj_enable { self.scan_oam(self.ly) } else { vec![] },
+			// The first tile fetch of the line is thrown away.
+			stall: 6,
+			..Default::default()
+		};
+	}
+
+	// Runs the FIFO for one dot, and returns true once the line is done.
+	pub(super) fn fifo_dot(&mut self) -> bool {
+		if self.fifo.stall > 0 {
+			self.fifo.stall -= 1;
+
+			if self.fifo.stall == 0 {
+				if let Some(sprite) = self.fifo.pending_sprite.take() {
+					self.merge_sprite(&sprite);
+				}
+			}
+
+			return false;
+		}
+
+		self.check_window();
+
+		if self.fifo.discard == 0 && self.check_sprite() {
+			return false;
+		}
+
+		self.fetcher_dot();
+
+		if let Some(color) = self.fifo.bg.pop_front() {
+			if self.fifo.discard > 0 {
+				self.fifo.discard -= 1;
+			} else {
+				let obj = self.fifo.obj.pop_front().unwrap_or_default();
+				self.output_pixel(color, obj);
+				self.fifo.x += 1;
+			}
+		}
+
+		if self.fifo.x == 160 {
+			// WX=166 makes the window cover the whole next line.
+			self.window_full_line = self.window_enable && self.wy_triggered && self.wx == 166;
+
+			if self.fifo.window_drawn {
+				self.window_line = self.window_line.wrapping_add(1);
+			}
+
+			return true;
+		}
+
+		false
+	}
+
+	fn check_window(&mut self) {
+		if self.fifo.window || !self.window_enable || !self.bg_window_enable || !self.wy_triggered {
+			return;
+		}
+
+		// WX<7 starts the window right away, cutting its first pixels.
+		let start = if self.window_full_line { 0 } else { self.wx as i16 - 7 };
+		if (self.window_full_line || self.wx <= 166) && self.fifo.discard == 0 && (self.fifo.x as i16) >= start.max(0) {
+			self.fifo.bg.clear();
+			self.fifo.window = true;
+			self.fifo.window_drawn = true;
+			self.fifo.fetch_dot = 0;
+			self.fifo.fetch_x = 0;
+			self.fifo.discard = (-start).max(0) as u8;
+		}
+	}
+
+	// Starts fetching the next sprite on the current pixel, if any.
+	fn check_sprite(&mut self) -> bool {
+		let x = self.fifo.x;
+		let position = self.fifo.sprites.iter()
+			.position(|sprite| sprite.x.saturating_sub(8) == x && sprite.x < 168);
+
+		match position {
+			Some(index) => {
+				let sprite = self.fifo.sprites.remove(index);
+
+				// The background fetch in progress has to end first.
+				self.fifo.stall = 6 + 5u8.saturating_sub(self.fifo.fetch_dot);
+				self.fifo.pending_sprite = Some(sprite);
+				true
+			},
+			None => false,
+		}
+	}
+
+	fn merge_sprite(&mut self, sprite: &Sprite) {
+		let pixels = self.sprite_pixels(sprite, self.ly);
+
+		// Sprites partially off the left of the screen lose their first pixels.
+		let skip = 8u8.saturating_sub(sprite.x) as usize;
+
+		while self.fifo.obj.len() < 8 - skip {
+			self.fifo.obj.push_back(ObjPixel::default());
+		}
+
+		// Pixels already in the FIFO come from sprites with a higher priority.
+		for (slot, &color) in self.fifo.obj.iter_mut().zip(pixels[skip..].iter()) {
+			if slot.color == 0 {
+				*slot = ObjPixel {
+					color,
+					palette: (sprite.flags & 0x10) != 0,
+					behind_bg: (sprite.flags & 0x80) != 0,
+				};
+			}
+		}
+	}
+
+	// The fetcher takes 2 dots for each of the tile number, and both
+	// data bytes, then waits for the FIFO to be empty to push the row.
+	fn fetcher_dot(&mut self) {
+		self.fifo.fetch_dot += 1;
+
+		match self.fifo.fetch_dot {
+			2 => self.fifo.tile = self.fetch_tile_number(),
+			4 => {
+				let y = self.fetch_y();
+				self.fifo.data = self.get_tile_row(self.fifo.tile, y);
+			},
+			6..=u8::MAX if self.fifo.bg.is_empty() => {
+				let (data1, data2) = self.fifo.data;
+
+				for bit in (0..8).rev() {
+					self.fifo.bg.push_back(((data1 >> bit) & 1) | (((data2 >> bit) & 1) << 1));
+				}
+
+				self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+				self.fifo.fetch_dot = 0;
+			},
+			_ => {},
+		}
+	}
+
+	fn fetch_y(&self) -> u8 {
+		if self.fifo.window {
+			self.window_line
+		} else {
+			self.scy.wrapping_add(self.ly)
+		}
+	}
+
+	fn fetch_tile_number(&self) -> u8 {
+		let y = self.fetch_y() as usize;
+
+		let (map, x) = if self.fifo.window {
+			(self.window_map, self.fifo.fetch_x as usize)
+		} else {
+			(self.bg_map, ((self.scx >> 3) as usize + self.fifo.fetch_x as usize) & 0x1F)
+		};
+
+		let base = if map { 0x1C00 } else { 0x1800 };
+		self.vram[base + x + ((y & 0xf8) << 2)]
+	}
+
+	// Registers are read when the pixel is output, so changes made
+	// during mode 3 show up on the rest of the line.
+	fn output_pixel(&mut self, color: u8, obj: ObjPixel) {
+		let color = if self.bg_window_enable { color } else { 0 };
+
+		let real_color = if self.obj_enable && obj.color != 0 && (!obj.behind_bg || color == 0) {
+			let palette = if obj.palette { &self.obp1 } else { &self.obp0 };
+			palette[obj.color as usize]
+		} else {
+			self.bgp[color as usize]
+		};
+
+		self.set_pixel(self.fifo.x, self.ly, real_color);
+	}
+}
@@ -1,16 +1,40 @@
 mod render;
+mod fifo;
+
+/// How the PPU draws lines.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PPUAccuracy {
+	/// Each line is drawn at once when entering HBlank, and mode 3 has a fixed length.
+	Scanline,
+	/// Pixels are drawn one dot at a time through a pixel FIFO, so changes
+	/// made during mode 3 show up, and mode 3 has its real length.
+	PixelFifo,
+}
 
 pub struct PPU {
 	vram: [u8; 0x2000],
 	oam: [u8; 0xA0],
-	pub buffer: Box<[u8; (160 * 144 * 4)]>,
+	pub buffer: Box<[u8; 160 * 144 * 4]>,
+	accuracy: PPUAccuracy,
+	access_restrictions: bool,
+	fifo: fifo::Fifo,
 	mode: PPUMode,
 	frame_done: bool,
+	first_frame: bool,
 	clock: u32,
+	// Internal line counter, which differs from LY on line 153
+	line: u8,
 	ly: u8,
+	// Value LY is compared against LYC with, if the comparison is done
+	ly_compare: Option<u8>,
 	wx: u8,
 	wy: u8,
 
+	// Window internal state
+	window_line: u8,
+	wy_triggered: bool,
+	window_full_line: bool,
+
 	// LCDC
 	enable: bool,
 	window_map: bool,
@@ -27,6 +51,8 @@ pub struct PPU {
 	mode2_irq: bool,
 	mode1_irq: bool,
 	mode0_irq: bool,
+	stat_line: bool,
+	oam_irq_pulse: bool,
 
 	scy: u8,
 	scx: u8,
@@ -45,75 +71,192 @@ impl PPU {
 
 	pub fn has_vblank_irq(&self) -> bool { self.vblank_irq }
 	pub fn ack_vblank_irq(&mut self) { self.vblank_irq = false; }
-	pub fn set_vblank_irq(&mut self, value: bool) { self.vblank_irq = value; }
 
 	pub fn has_stat_irq(&self) -> bool { self.stat_irq }
 	pub fn ack_stat_irq(&mut self) { self.stat_irq = false; }
-	pub fn set_stat_irq(&mut self, value: bool) { self.stat_irq = value; }
+
+	pub fn set_accuracy(&mut self, accuracy: PPUAccuracy) { self.accuracy = accuracy; }
+	pub fn set_access_restrictions(&mut self, enabled: bool) { self.access_restrictions = enabled; }
+
+	// The CPU can't access VRAM while the PPU draws, nor OAM while it
+	// scans or draws. Reads return 0xFF, and writes are ignored.
+	pub fn vram_accessible(&self) -> bool {
+		!self.access_restrictions || self.mode != PPUMode::Drawing
+	}
+
+	pub fn oam_accessible(&self) -> bool {
+		!self.access_restrictions || !matches!(self.mode, PPUMode::ReadingOAM | PPUMode::Drawing)
+	}
+
+	fn fifo_drawing(&self) -> bool {
+		self.accuracy == PPUAccuracy::PixelFifo && self.mode == PPUMode::Drawing
+	}
 
 	pub fn spend(&mut self, cycles: u32) {
+		// With the LCD off, blank frames are still presented at the usual rate.
 		if !self.enable {
+			self.clock += cycles;
+
+			if self.clock >= DOTS_PER_FRAME {
+				self.clock -= DOTS_PER_FRAME;
+				self.clear_buffer();
+				self.frame_done = true;
+			}
+
 			return;
 		}
 
-		self.clock += cycles;
-		match self.mode {
-			PPUMode::HBlank => {
-				if self.clock >= 456 {
-					self.render_line(self.ly);
-					self.increment_line();
-					self.clock -= 456;
-
-					if self.ly == 144 {
-						self.mode = PPUMode::VBlank;
-						
-						if self.mode1_irq {
-							self.stat_irq = true;
-						}
-					} else {
-						self.mode = PPUMode::ReadingOAM;
-					}
+		// Jump from one event of the line to the next, or go dot
+		// by dot while the pixel FIFO is running.
+		let mut cycles = cycles;
+		while cycles > 0 {
+			if self.fifo_drawing() {
+				self.clock += 1;
+				cycles -= 1;
+
+				if self.fifo_dot() {
+					self.mode = PPUMode::HBlank;
+					self.update_stat_line();
 				}
+
+				continue;
+			}
+
+			let next_event = LINE_EVENTS.iter()
+				.copied()
+				.find(|&dot| dot > self.clock)
+				.unwrap_or(456);
+			let step = cycles.min(next_event - self.clock);
+
+			self.clock += step;
+			cycles -= step;
+
+			if self.clock == next_event {
+				self.line_event();
+			}
+		}
+	}
+
+	fn line_event(&mut self) {
+		match (self.line, self.clock) {
+			(_, 456) => {
+				self.clock = 0;
+				self.start_line();
 			},
-			PPUMode::VBlank => {
-				if self.clock >= 456 {
-					self.increment_line();
-					self.clock -= 456;
-
-					if self.ly == 0 {
-						self.frame_done = true;
-						self.mode = PPUMode::ReadingOAM;
-
-						if self.mode2_irq {
-							self.stat_irq = true;
-						}
-					}
-				}
+			// LY only reads 153 for the first few dots of the last line,
+			// so both LYC=153 and LYC=0 match on that line.
+			(153, 4) => self.ly_compare = Some(153),
+			(153, 8) => {
+				self.ly = 0;
+				self.ly_compare = None;
 			},
-			PPUMode::ReadingOAM => {
-				if self.clock >= 80 {
-					self.mode = PPUMode::Drawing;
+			(153, 12) | (_, 4) => self.ly_compare = Some(self.ly),
+			(0..=143, 80) => {
+				self.mode = PPUMode::Drawing;
+
+				if self.accuracy == PPUAccuracy::PixelFifo {
+					self.start_fifo_line();
 				}
 			},
-			PPUMode::Drawing => {
-				if self.clock >= 310 {
-					self.mode = PPUMode::HBlank;
+			(0..=143, 252) if self.accuracy == PPUAccuracy::Scanline => {
+				self.mode = PPUMode::HBlank;
+				self.render_line(self.ly);
+			},
+			_ => {},
+		}
 
-					if self.mode0_irq {
-						self.stat_irq = true;
-					}
-				}
-			}
+		self.update_stat_line();
+	}
+
+	fn start_line(&mut self) {
+		self.line = (self.line + 1) % 154;
+		self.oam_irq_pulse = false;
+
+		// The comparison is cleared for a few dots when LY changes.
+		if self.line != self.ly {
+			self.ly = self.line;
+			self.ly_compare = None;
 		}
 
+		match self.line {
+			0 => {
+				// The first frame after the LCD is turned on isn't shown.
+				if self.first_frame {
+					self.first_frame = false;
+					self.clear_buffer();
+				}
+
+				self.frame_done = true;
+				self.window_line = 0;
+				self.wy_triggered = false;
+				self.window_full_line = false;
+				self.mode = PPUMode::ReadingOAM;
+			},
+			1..=143 => self.mode = PPUMode::ReadingOAM,
+			144 => {
+				self.mode = PPUMode::VBlank;
+				self.vblank_irq = true;
+
+				// The mode 2 source also fires when entering VBlank.
+				self.oam_irq_pulse = true;
+			},
+			_ => {},
+		}
 	}
 
-	fn increment_line(&mut self) {
-		self.ly = (self.ly + 1) % 155;
+	// The STAT interrupt is requested on rising edges of the OR of all
+	// the enabled sources, so a source going high while another one
+	// already is doesn't trigger anything.
+	fn update_stat_line(&mut self) {
+		let line = (self.mode0_irq && self.mode == PPUMode::HBlank) ||
+			(self.mode1_irq && self.mode == PPUMode::VBlank) ||
+			(self.mode2_irq && (self.mode == PPUMode::ReadingOAM || self.oam_irq_pulse)) ||
+			(self.coincidence_irq && self.lyc_match());
 
-		if self.coincidence_irq && self.ly == self.lyc {
+		if line && !self.stat_line {
 			self.stat_irq = true;
 		}
+
+		self.stat_line = line;
+	}
+
+	fn clear_buffer(&mut self) {
+		let blank = DmgColor::White.as_real();
+
+		for pixel in self.buffer.chunks_mut(4) {
+			pixel.copy_from_slice(&[blank[3], blank[2], blank[1], blank[0]]);
+		}
+	}
+
+	// The boot ROM leaves the LCD on.
+	pub fn skip_boot(&mut self) {
+		self.enable = true;
+	}
+
+	fn set_enable(&mut self, enable: bool) {
+		if enable == self.enable {
+			return;
+		}
+
+		self.enable = enable;
+		self.clock = 0;
+		self.line = 0;
+		self.ly = 0;
+		self.ly_compare = Some(0);
+
+		// LY stays at 0 and the PPU in mode 0 while the LCD is off. When
+		// turning it back on, the first line has no OAM scan.
+		self.mode = PPUMode::HBlank;
+		self.first_frame = enable;
+		self.update_stat_line();
+	}
+
+	fn lyc_match(&self) -> bool {
+		self.ly_compare == Some(self.lyc)
+	}
+
+	pub fn read_vram_u8(&self, addr: u16) -> u8 {
+		self.vram[(addr as usize) - 0x8000]
 	}
 
 	pub fn write_vram_u8(&mut self, addr: u16, value: u8) {
@@ -142,20 +285,30 @@ impl PPU {
 				(self.obj_size as u8) << 2 |
 				(self.obj_enable as u8) << 1 |
 				(self.bg_window_enable as u8),
+			0xff41 => 0x80 |
+				(self.coincidence_irq as u8) << 6 |
+				(self.mode2_irq as u8) << 5 |
+				(self.mode1_irq as u8) << 4 |
+				(self.mode0_irq as u8) << 3 |
+				(self.lyc_match() as u8) << 2 |
+				(self.mode as u8),
 			0xff42 => self.scy,
 			0xff43 => self.scx,
 			0xff44 => self.ly,
-			_ => unimplemented!(
-				"PPU I/O register unimplemented : {:04x}",
-				addr
-			),
+			0xff45 => self.lyc,
+			0xff47 => self.bgp.get_register(),
+			0xff48 => self.obp0.get_register(),
+			0xff49 => self.obp1.get_register(),
+			0xff4a => self.wy,
+			0xff4b => self.wx,
+			_ => unreachable!(),
 		}
 	}
 
 	pub fn write_io_register(&mut self, addr: u16, value: u8) {
 		match addr {
 			0xff40 => {
-				self.enable = (value & 0x80) != 0;
+				self.set_enable((value & 0x80) != 0);
 				self.window_map = (value & 0x40) != 0;
 				self.window_enable = (value & 0x20) != 0;
 				self.tile_data = (value & 0x10) != 0;
@@ -165,24 +318,34 @@ impl PPU {
 				self.bg_window_enable = (value & 0x01) != 0;
 			},
 			0xff41 => {
+				// On DMG, the HBlank, VBlank and LY=LYC sources are briefly
+				// enabled during the write, which can trigger a spurious
+				// interrupt.
+				self.mode0_irq = true;
+				self.mode1_irq = true;
+				self.coincidence_irq = true;
+				self.update_stat_line();
+
 				self.mode0_irq = (value & 0x08) != 0;
 				self.mode1_irq = (value & 0x10) != 0;
 				self.mode2_irq = (value & 0x20) != 0;
 				self.coincidence_irq = (value & 0x40) != 0;
+				self.update_stat_line();
 			},
 			0xff42 => self.scy = value,
 			0xff43 => self.scx = value,
-			0xff45 => self.lyc = value,
+			// LY is read-only
+			0xff44 => {},
+			0xff45 => {
+				self.lyc = value;
+				self.update_stat_line();
+			},
 			0xff47 => self.bgp.set_register(value),
 			0xff48 => self.obp0.set_register(value),
 			0xff49 => self.obp1.set_register(value),
 			0xff4a => self.wy = value,
 			0xff4b => self.wx = value,
-			_ => unimplemented!(
-				"PPU I/O register unimplemented : {:04x}, {:02x}",
-				addr,
-				value
-			),
+			_ => unreachable!(),
 		}
 	}
 }
@@ -192,14 +355,24 @@ impl Default for PPU {
 		PPU {
 			vram: [0; 0x2000],
 			oam: [0; 0xA0],
-			buffer: Box::new([0; (160 * 144 * 4)]),
+			buffer: Box::new([0; 160 * 144 * 4]),
+			accuracy: PPUAccuracy::Scanline,
+			access_restrictions: true,
+			fifo: Default::default(),
 			frame_done: false,
+			first_frame: false,
 			clock: 0,
 			mode: PPUMode::ReadingOAM,
+			line: 0,
 			ly: 0,
+			ly_compare: Some(0),
 			wx: 0,
 			wy: 0,
 
+			window_line: 0,
+			wy_triggered: false,
+			window_full_line: false,
+
 			// LCDC
 			enable: false,
 			window_map: false,
@@ -216,6 +389,8 @@ impl Default for PPU {
 			mode2_irq: false,
 			mode1_irq: false,
 			mode0_irq: false,
+			stat_line: false,
+			oam_irq_pulse: false,
 
 			scy: 0,
 			scx: 0,
@@ -228,6 +403,12 @@ impl Default for PPU {
 	}
 }
 
+const DOTS_PER_FRAME: u32 = 456 * 154;
+
+// Dots at which something happens during a line
+const LINE_EVENTS: [u32; 6] = [4, 8, 12, 80, 252, 456];
+
+#[derive(Copy, Clone, PartialEq)]
 enum PPUMode {
 	HBlank = 0,
 	VBlank = 1,
@@ -1,43 +1,193 @@
-use crate::ppu::PPU;
+use crate::ppu::{PPU, DmgColor};
+
+#[derive(Copy, Clone)]
+pub(super) struct Sprite {
+	pub x: u8,
+	pub y: u8,
+	pub tile: u8,
+	pub flags: u8,
+}
 
 impl PPU {
 	pub fn render_line(&mut self, line: u8) {
-		for x in 0..=159 {
-			let real_x = self.scx.wrapping_add(x) as usize;
-			let real_y = self.scy.wrapping_add(line) as usize;
+		// Color indexes of the background, used for sprite priority
+		let mut bg_colors = [0u8; 160];
+
+		if line == self.wy {
+			self.wy_triggered = true;
+		}
 
-			let tile = if self.bg_map {
-				self.vram[0x1C00 + (real_x >> 3) + ((real_y & 0xf8) << 2)]
-			} else {
-				self.vram[0x1800 + (real_x >> 3) + ((real_y & 0xf8) << 2)]
+		let window_start = self.window_start();
+
+		for x in 0..=159 {
+			let color = match window_start {
+				_ if !self.bg_window_enable => 0,
+				Some(start) if (x as i16) >= start => {
+					self.get_window_pixel((x as i16 - start) as u8)
+				},
+				_ => self.get_bg_pixel(x, line),
 			};
 
-			let tile_color = self.get_pixel_from_tile(tile, real_x as u8, real_y as u8);
-			let real_color = self.bgp[tile_color as usize].as_real();
+			bg_colors[x as usize] = color;
+			self.set_pixel(x, line, self.bgp[color as usize]);
+		}
+
+		// WX=166 makes the window cover the whole next line.
+		self.window_full_line = window_start.is_some() && self.wx == 166;
+
+		// The internal line counter only moves when the window was drawn.
+		if window_start.is_some() && self.bg_window_enable {
+			self.window_line = self.window_line.wrapping_add(1);
+		}
+
+		if self.obj_enable {
+			self.render_sprites(line, &bg_colors);
+		}
+	}
+
+	// Screen X coordinate at which the window starts on this line, which
+	// can be negative when the window is scrolled to the left.
+	fn window_start(&self) -> Option<i16> {
+		if !self.window_enable || !self.wy_triggered {
+			return None;
+		}
+
+		match self.wx {
+			_ if self.window_full_line => Some(0),
+			// With WX=0, the window is shifted further by SCX's fine scroll.
+			0 => Some(-7 - (self.scx & 0x07) as i16),
+			wx @ 1..=166 => Some(wx as i16 - 7),
+			_ => None,
+		}
+	}
 
-			self.buffer[((line as usize * 160) + x as usize) * 4] = real_color[3];
-			self.buffer[((line as usize * 160) + x as usize) * 4 + 1] = real_color[2];
-			self.buffer[((line as usize * 160) + x as usize) * 4 + 2] = real_color[1];
-			self.buffer[((line as usize * 160) + x as usize) * 4 + 3] = real_color[0];
+	fn get_window_pixel(&self, x: u8) -> u8 {
+		let y = self.window_line as usize;
+
+		let tile = if self.window_map {
+			self.vram[0x1C00 + (x as usize >> 3) + ((y & 0xf8) << 2)]
+		} else {
+			self.vram[0x1800 + (x as usize >> 3) + ((y & 0xf8) << 2)]
 		};
+
+		self.get_pixel_from_tile(tile, x, y as u8)
 	}
 
-	fn get_pixel_from_tile(&self, tile: u8, x: u8, y: u8) -> u8 {
-		let tile_x = x & 0x7;
+	fn get_bg_pixel(&self, x: u8, line: u8) -> u8 {
+		let real_x = self.scx.wrapping_add(x) as usize;
+		let real_y = self.scy.wrapping_add(line) as usize;
+
+		let tile = if self.bg_map {
+			self.vram[0x1C00 + (real_x >> 3) + ((real_y & 0xf8) << 2)]
+		} else {
+			self.vram[0x1800 + (real_x >> 3) + ((real_y & 0xf8) << 2)]
+		};
+
+		self.get_pixel_from_tile(tile, real_x as u8, real_y as u8)
+	}
+
+	// Returns the sprites on this line, in DMG priority order: the
+	// lowest X coordinate wins, then the one that comes first in OAM.
+	pub(super) fn scan_oam(&self, line: u8) -> Vec<Sprite> {
+		let height = if self.obj_size { 16 } else { 8 };
+
+		// Only the first 10 sprites found on the line are drawn.
+		let mut sprites: Vec<(usize, Sprite)> = self.oam.chunks(4)
+			.map(|data| Sprite { y: data[0], x: data[1], tile: data[2], flags: data[3] })
+			.enumerate()
+			.filter(|(_, sprite)| {
+				let top = sprite.y as i16 - 16;
+				(line as i16) >= top && (line as i16) < top + height
+			})
+			.take(10)
+			.collect();
+
+		sprites.sort_by_key(|&(index, ref sprite)| (sprite.x, index));
+		sprites.into_iter().map(|(_, sprite)| sprite).collect()
+	}
+
+	// Color indexes of the 8 pixels of the sprite on this line, left to right
+	pub(super) fn sprite_pixels(&self, sprite: &Sprite, line: u8) -> [u8; 8] {
+		let height = if self.obj_size { 16 } else { 8 };
+
+		let mut row = (line as i16 - (sprite.y as i16 - 16)) as u8;
+		if (sprite.flags & 0x40) != 0 {
+			row = height - 1 - row;
+		}
+
+		let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+		let offset = ((tile as usize) << 4) + ((row as usize) << 1);
+		let (data1, data2) = (self.vram[offset], self.vram[offset + 1]);
+
+		let mut pixels = [0; 8];
+		for (column, pixel) in pixels.iter_mut().enumerate() {
+			let bit = if (sprite.flags & 0x20) != 0 { column } else { 7 - column };
+			*pixel = ((data1 >> bit) & 1) | (((data2 >> bit) & 1) << 1);
+		}
+
+		pixels
+	}
+
+	fn render_sprites(&mut self, line: u8, bg_colors: &[u8; 160]) {
+		let sprites = self.scan_oam(line);
+
+		// Sprites are drawn from the lowest priority to the highest, so that
+		// the pixel left is the first opaque one in priority order.
+		let mut pixels: [Option<(DmgColor, bool)>; 160] = [None; 160];
+		for sprite in sprites.iter().rev() {
+			let palette = if (sprite.flags & 0x10) != 0 { &self.obp1 } else { &self.obp0 };
+			let behind_bg = (sprite.flags & 0x80) != 0;
+
+			for (column, &color) in self.sprite_pixels(sprite, line).iter().enumerate() {
+				let x = sprite.x as i16 - 8 + column as i16;
+				if !(0..160).contains(&x) {
+					continue;
+				}
+
+				// Color 0 is transparent
+				if color != 0 {
+					pixels[x as usize] = Some((palette[color as usize], behind_bg));
+				}
+			}
+		}
+
+		for (x, pixel) in pixels.iter().enumerate() {
+			if let Some((color, behind_bg)) = *pixel {
+				if !behind_bg || bg_colors[x] == 0 {
+					self.set_pixel(x as u8, line, color);
+				}
+			}
+		}
+	}
+
+	pub(super) fn set_pixel(&mut self, x: u8, line: u8, color: DmgColor) {
+		let real_color = color.as_real();
+		let offset = ((line as usize * 160) + x as usize) * 4;
+
+		self.buffer[offset] = real_color[3];
+		self.buffer[offset + 1] = real_color[2];
+		self.buffer[offset + 2] = real_color[1];
+		self.buffer[offset + 3] = real_color[0];
+	}
+
+	// Both bytes of a row of a background or window tile
+	pub(super) fn get_tile_row(&self, tile: u8, y: u8) -> (u8, u8) {
 		let tile_y = y & 0x7;
 
 		let offset = if self.tile_data {
 			((tile as usize) << 4) + ((tile_y as usize) << 1)
 		} else {
 			let tile = tile as i8;
-			(0x1000 + ((tile as isize) << 4) + ((y as isize) << 1)) as usize
+			(0x1000 + ((tile as isize) << 4) + ((tile_y as isize) << 1)) as usize
 		};
 
-		let (data1, data2) = (
-			self.vram[offset],
-			self.vram[offset + 1]
-		);
+		(self.vram[offset], self.vram[offset + 1])
+	}
+
+	fn get_pixel_from_tile(&self, tile: u8, x: u8, y: u8) -> u8 {
+		let tile_x = x & 0x7;
+		let (data1, data2) = self.get_tile_row(tile, y);
 
 		(data1 >> (7 - tile_x) & 1) | (((data2 >> (7 - tile_x)) & 1) << 1)
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,60 @@
+#[derive(Default)]
+pub struct Serial {
+	data: u8,
+	transfer: bool,
+	internal_clock: bool,
+	clock: u32,
+	bits: u8,
+
+	// Interruptions
+	irq: bool,
+}
+
+impl Serial {
+	pub fn has_irq(&self) -> bool { self.irq }
+	pub fn ack_irq(&mut self) { self.irq = false; }
+
+	pub fn spend(&mut self, cycles: u32) {
+		// With an external clock and nothing plugged in, the transfer
+		// never progresses.
+		if !self.transfer || !self.internal_clock {
+			return;
+		}
+
+		self.clock += cycles;
+		while self.clock >= 512 {
+			self.clock -= 512;
+
+			// Nothing is connected, so only ones are shifted in.
+			self.data = (self.data << 1) | 1;
+			self.bits += 1;
+
+			if self.bits == 8 {
+				self.transfer = false;
+				self.irq = true;
+				break;
+			}
+		}
+	}
+
+	pub fn read_io_register(&self, addr: u16) -> u8 {
+		match addr {
+			0xff01 => self.data,
+			0xff02 => 0x7E | (self.transfer as u8) << 7 | (self.internal_clock as u8),
+			_ => unreachable!(),
+		}
+	}
+
+	pub fn write_io_register(&mut self, addr: u16, value: u8) {
+		match addr {
+			0xff01 => self.data = value,
+			0xff02 => {
+				self.transfer = (value & 0x80) != 0;
+				self.internal_clock = (value & 0x01) != 0;
+				self.clock = 0;
+				self.bits = 0;
+			},
+			_ => unreachable!(),
+		}
+	}
+}
@@ -0,0 +1,111 @@
+#[derive(Default)]
+pub struct Timer {
+	// The internal 16-bit counter, DIV being its upper byte
+	div: u16,
+	tima: u8,
+	tma: u8,
+	tac: u8,
+
+	// TIMA overflowed on the last cycle and will be reloaded on this one
+	reload_pending: bool,
+	// TIMA has been reloaded during the current cycle
+	reloading: bool,
+
+	// Interruptions
+	irq: bool,
+}
+
+impl Timer {
+	pub fn has_irq(&self) -> bool { self.irq }
+	pub fn ack_irq(&mut self) { self.irq = false; }
+
+	pub fn divider(&self) -> u16 { self.div }
+	pub fn set_divider(&mut self, div: u16) { self.div = div; }
+
+	pub fn spend(&mut self, cycles: u32) {
+		for _ in 0..(cycles >> 2) {
+			self.tick();
+		}
+	}
+
+	fn tick(&mut self) {
+		self.reloading = false;
+
+		// TIMA stays at 0 for one cycle after overflowing.
+		if self.reload_pending {
+			self.reload_pending = false;
+			self.reloading = true;
+			self.tima = self.tma;
+			self.irq = true;
+		}
+
+		let old_signal = self.signal();
+		self.div = self.div.wrapping_add(4);
+		self.check_falling_edge(old_signal);
+	}
+
+	// The bit of the divider selected by TAC, ANDed with the enable bit
+	fn signal(&self) -> bool {
+		let bit = match self.tac & 0x03 {
+			0 => 9,
+			1 => 3,
+			2 => 5,
+			3 => 7,
+			_ => unreachable!(),
+		};
+
+		(self.tac & 0x04) != 0 && (self.div >> bit) & 1 != 0
+	}
+
+	fn check_falling_edge(&mut self, old_signal: bool) {
+		if old_signal && !self.signal() {
+			let (tima, overflow) = self.tima.overflowing_add(1);
+
+			self.tima = tima;
+			self.reload_pending = overflow;
+		}
+	}
+
+	pub fn read_io_register(&self, addr: u16) -> u8 {
+		match addr {
+			0xff04 => (self.div >> 8) as u8,
+			0xff05 => self.tima,
+			0xff06 => self.tma,
+			0xff07 => 0xF8 | self.tac,
+			_ => unreachable!(),
+		}
+	}
+
+	pub fn write_io_register(&mut self, addr: u16, value: u8) {
+		// Resetting DIV or changing TAC can produce a falling edge
+		// on the selected bit, which increments TIMA.
+		match addr {
+			0xff04 => {
+				let old_signal = self.signal();
+				self.div = 0;
+				self.check_falling_edge(old_signal);
+			},
+			0xff05 => {
+				// Writes are ignored on the reload cycle, and cancel
+				// the reload if done during the cycle before.
+				if !self.reloading {
+					self.tima = value;
+					self.reload_pending = false;
+				}
+			},
+			0xff06 => {
+				self.tma = value;
+
+				if self.reloading {
+					self.tima = value;
+				}
+			},
+			0xff07 => {
+				let old_signal = self.signal();
+				self.tac = value & 0x07;
+				self.check_falling_edge(old_signal);
+			},
+			_ => unreachable!(),
+		}
+	}
+}
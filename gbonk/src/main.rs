@@ -3,10 +3,35 @@ extern crate sdl2;
 extern crate gback;
 
 mod platform;
+mod wav;
 
 use clap::{App, Arg};
 use std::fs::File;
-use gback::Gameboy;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use gback::{Gameboy, GBStatus, Model};
+use gback::ppu::PPUAccuracy;
+use gback::apu::{AudioConfig, HighPass};
+
+// Battery-backed RAM is written back every 10 seconds or so
+const SAVE_INTERVAL: u32 = 600;
+
+// A frame lasts 70224 dots at 4 MiHz
+const FRAME_DURATION: Duration = Duration::from_nanos(70224 * 1_000_000_000 / 4_194_304);
+
+fn write_save(gameboy: &Gameboy, path: &Path, last_save: &mut Option<Vec<u8>>) {
+    if let Some(data) = gameboy.save_data() {
+        if last_save.as_ref() == Some(&data) {
+            return;
+        }
+
+        match std::fs::write(path, &data) {
+            Ok(()) => *last_save = Some(data),
+            Err(e) => eprintln!("Couldn't write save file {}: {}", path.display(), e),
+        }
+    }
+}
 
 fn main() -> std::io::Result<()> {
     let matches = App::new("GBonk")
@@ -16,27 +41,140 @@ fn main() -> std::io::Result<()> {
         .arg(Arg::with_name("BOOTROM")
             .short("b")
             .long("bootrom")
-            .required(true)
             .value_name("bootrom"))
+        .arg(Arg::with_name("MODEL")
+            .short("m")
+            .long("model")
+            .help("Model to emulate the post-boot state and audio filter of")
+            .possible_values(&["dmg0", "dmg", "mgb", "sgb", "cgb"])
+            .default_value("dmg")
+            .value_name("model"))
+        .arg(Arg::with_name("PPU")
+            .long("ppu")
+            .help("Scanline renderer, or the slower but more accurate pixel FIFO")
+            .possible_values(&["scanline", "fifo"])
+            .default_value("scanline")
+            .value_name("renderer"))
+        .arg(Arg::with_name("NO_ACCESS_RESTRICTIONS")
+            .long("no-access-restrictions")
+            .help("Let the CPU access VRAM and OAM while the PPU uses them"))
+        .arg(Arg::with_name("SYNC")
+            .long("sync")
+            .help("Pace the emulation on the frame rate, or on audio playback")
+            .possible_values(&["video", "audio"])
+            .default_value("video")
+            .value_name("clock"))
+        .arg(Arg::with_name("RECORD_AUDIO")
+            .long("record-audio")
+            .help("Record the audio to a .wav file, F5 toggling the recording")
+            .value_name("file"))
         .arg(Arg::with_name("ROM")
             .required(true)
             .value_name("rom"))
         .get_matches();
 
-    let bootrom_fn = matches.value_of("BOOTROM").unwrap();
     let rom_fn = matches.value_of("ROM").unwrap();
-    let bootrom = File::open(bootrom_fn)?;
-    let rom = File::open(rom_fn)?;
+    let rom = std::fs::read(rom_fn)?;
+    let rom_len = rom.len();
     
+    let model = match matches.value_of("MODEL").unwrap() {
+        "dmg0" => Model::Dmg0,
+        "mgb" => Model::Mgb,
+        "sgb" => Model::Sgb,
+        "cgb" => Model::Cgb,
+        _ => Model::Dmg,
+    };
+
     let mut platform = platform::SDLPlatform::new();
-    let mut gameboy = Gameboy::new();
-    gameboy.load_bios(bootrom);
-    gameboy.load_rom(rom);
+    let mut gameboy = Gameboy::with_audio_config(AudioConfig {
+        sample_rate: platform.sample_rate(),
+        high_pass: if model == Model::Cgb { HighPass::Cgb } else { HighPass::Dmg },
+        ..Default::default()
+    });
+    if let Err(e) = gameboy.load_rom_vec(rom) {
+        eprintln!("Couldn't load {}: {}", rom_fn, e);
+        std::process::exit(1);
+    }
+
+    if let Some(header) = gameboy.cartridge_header() {
+        if rom_len > header.rom_size {
+            eprintln!(
+                "Warning: the header of {} announces {} bytes of ROM, but the file is {} bytes long",
+                rom_fn, header.rom_size, rom_len
+            );
+        }
+    }
+
+    if matches.value_of("PPU") == Some("fifo") {
+        gameboy.set_ppu_accuracy(PPUAccuracy::PixelFifo);
+    }
+
+    if matches.is_present("NO_ACCESS_RESTRICTIONS") {
+        gameboy.set_access_restrictions(false);
+    }
+
+    if let Some(bootrom_fn) = matches.value_of("BOOTROM") {
+        gameboy.load_bios(File::open(bootrom_fn)?)?;
+    } else {
+        gameboy.skip_boot(model);
+    }
     gameboy.running = true;
 
+    let save_path = Path::new(rom_fn).with_extension("sav");
+    if gameboy.save_data().is_some() {
+        match std::fs::read(&save_path) {
+            Ok(data) => gameboy.load_save_data(&data),
+            Err(e) if e.kind() == ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+    }
+    let mut last_save = gameboy.save_data();
+
+    platform.set_recording_base(&Path::new(rom_fn).with_extension(""));
+    if let Some(record_fn) = matches.value_of("RECORD_AUDIO") {
+        platform.start_recording(Path::new(record_fn))?;
+    }
+
+    let sync_audio = matches.value_of("SYNC") == Some("audio") && platform.has_audio();
+    if matches.value_of("SYNC") == Some("audio") && !sync_audio {
+        eprintln!("No audio device available, pacing on the frame rate instead");
+    }
+
+    let mut lockup_reported = false;
+    let mut frames = 0;
+    let mut next_frame = Instant::now();
     while gameboy.running {
-        gameboy.run_frame(&mut platform);
+        frames += 1;
+        if frames % SAVE_INTERVAL == 0 {
+            write_save(&gameboy, &save_path, &mut last_save);
+        }
+
+        if let GBStatus::CpuLockup { opcode, addr } = gameboy.run_frame(&mut platform) {
+            if !lockup_reported {
+                eprintln!("CPU locked up on illegal opcode {:02x} at {:04x}", opcode, addr);
+                lockup_reported = true;
+            }
+        }
+
+        if sync_audio {
+            platform.wait_for_audio();
+        } else {
+            next_frame += FRAME_DURATION;
+
+            // Don't try to catch up after falling behind
+            let now = Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+
+        gameboy.set_sample_rate(platform.sample_rate());
     }
 
+    write_save(&gameboy, &save_path, &mut last_save);
+    platform.stop_recording();
+
     Ok(())
 }
@@ -1,10 +1,28 @@
-use gback::{Platform, GBEvent};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use gback::{Platform, GBEvent, Button, Channel};
+use gback::apu::SAMPLE_RATE;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::surface::Surface;
+use crate::wav::WavWriter;
+
+// Amount of audio kept queued, in seconds
+const AUDIO_LATENCY: f64 = 0.05;
+// Largest change made to the sample rate to keep the queue at that level,
+// which is too small to be heard
+const MAX_RATE_DELTA: f64 = 0.005;
 
 pub struct SDLPlatform {
 	window: sdl2::video::Window,
 	event_pump: sdl2::EventPump,
+	controller: Option<sdl2::controller::GameController>,
+	audio: Option<AudioQueue<f32>>,
+
+	recording: Option<(WavWriter, PathBuf)>,
+	// Recordings started with the hotkey are named after this path
+	recording_base: PathBuf,
 }
 
 impl SDLPlatform {
@@ -19,13 +37,126 @@ impl SDLPlatform {
 
 		let event_pump = sdl_context.event_pump().unwrap();
 
+		// The first game controller found is used for rumble
+		let controller = sdl_context.game_controller().ok().and_then(|subsystem| {
+			let joysticks = subsystem.num_joysticks().unwrap_or(0);
+
+			(0..joysticks)
+				.filter(|&index| subsystem.is_game_controller(index))
+				.find_map(|index| subsystem.open(index).ok())
+		});
+
+		// The game still runs without sound if no audio device is available
+		let audio = sdl_context.audio().ok().and_then(|subsystem| {
+			let desired = AudioSpecDesired {
+				freq: Some(SAMPLE_RATE as i32),
+				channels: Some(2),
+				samples: Some(1024),
+			};
+
+			subsystem.open_queue::<f32, _>(None, &desired).ok()
+		});
+
+		if let Some(queue) = &audio {
+			queue.resume();
+		}
+
 		SDLPlatform {
 			window,
-			event_pump
+			event_pump,
+			controller,
+			audio,
+			recording: None,
+			recording_base: PathBuf::from("gbonk"),
+		}
+	}
+
+	pub fn set_recording_base(&mut self, path: &Path) {
+		self.recording_base = path.to_path_buf();
+	}
+
+	pub fn start_recording(&mut self, path: &Path) -> std::io::Result<()> {
+		self.stop_recording();
+
+		let sample_rate = match &self.audio {
+			Some(queue) => queue.spec().freq as u32,
+			None => SAMPLE_RATE,
+		};
+
+		let writer = WavWriter::create(path, sample_rate)?;
+		self.recording = Some((writer, path.to_path_buf()));
+		eprintln!("Recording audio to {}", path.display());
+
+		Ok(())
+	}
+
+	pub fn stop_recording(&mut self) {
+		if let Some((writer, path)) = self.recording.take() {
+			match writer.finish() {
+				Ok(()) => eprintln!("Audio recorded to {}", path.display()),
+				Err(e) => eprintln!("Couldn't write {}: {}", path.display(), e),
+			}
+		}
+	}
+
+	fn toggle_recording(&mut self) {
+		if self.recording.is_some() {
+			self.stop_recording();
+			return;
+		}
+
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|time| time.as_secs())
+			.unwrap_or(0);
+
+		let mut name = self.recording_base.clone().into_os_string();
+		name.push(format!("-{}.wav", timestamp));
+		let path = PathBuf::from(name);
+
+		if let Err(e) = self.start_recording(&path) {
+			eprintln!("Couldn't create {}: {}", path.display(), e);
+		}
+	}
+
+	pub fn has_audio(&self) -> bool {
+		self.audio.is_some()
+	}
+
+	// Sample rate to ask the emulator for. It is raised when the queue
+	// is running low and lowered when it fills up, so that audio neither
+	// underruns nor drifts away from the video.
+	pub fn sample_rate(&self) -> u32 {
+		match &self.audio {
+			Some(queue) => {
+				let rate = queue.spec().freq as f64;
+				let fill = queued_frames(queue) / (rate * AUDIO_LATENCY);
+				let delta = (1.0 - fill).clamp(-1.0, 1.0) * MAX_RATE_DELTA;
+
+				(rate * (1.0 + delta)) as u32
+			},
+			None => SAMPLE_RATE,
+		}
+	}
+
+	// Blocks until the audio queue drops to its target level, which makes
+	// audio playback the master clock.
+	pub fn wait_for_audio(&self) {
+		if let Some(queue) = &self.audio {
+			let target = queue.spec().freq as f64 * AUDIO_LATENCY;
+
+			while queued_frames(queue) > target {
+				std::thread::sleep(Duration::from_millis(1));
+			}
 		}
 	}
 }
 
+// Number of stereo samples waiting to be played
+fn queued_frames(queue: &AudioQueue<f32>) -> f64 {
+	(queue.size() as usize / (2 * std::mem::size_of::<f32>())) as f64
+}
+
 impl Platform for SDLPlatform {
 	fn present_buffer(&mut self, buffer: &mut [u8]) {
 		if let Ok(mut window_surface) = self.window.surface(&self.event_pump) {
@@ -47,10 +178,86 @@ impl Platform for SDLPlatform {
 	}
 
 	fn process_events(&mut self) -> Option<GBEvent> {
-		match self.event_pump.poll_event() {
-			Some(Event::Quit {..}) => Some(GBEvent::Quit),
-			None => None,
-			_ => None
+		// Skip events we don't care about, as returning None stops
+		// the processing for this frame.
+		while let Some(event) = self.event_pump.poll_event() {
+			match event {
+				Event::Quit {..} => return Some(GBEvent::Quit),
+				Event::KeyDown { keycode: Some(keycode), keymod, repeat: false, .. } => {
+					if let Some(button) = keycode_to_button(keycode) {
+						return Some(GBEvent::ButtonDown(button));
+					}
+
+					if keycode == Keycode::F5 {
+						self.toggle_recording();
+					}
+
+					// 1 to 4 mute a channel, and solo it with Shift held
+					if let Some(channel) = keycode_to_channel(keycode) {
+						if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+							return Some(GBEvent::ToggleSolo(channel));
+						}
+
+						return Some(GBEvent::ToggleMute(channel));
+					}
+				},
+				Event::KeyUp { keycode: Some(keycode), .. } => {
+					if let Some(button) = keycode_to_button(keycode) {
+						return Some(GBEvent::ButtonUp(button));
+					}
+				},
+				_ => {}
+			}
 		}
+
+		None
 	}
-}
\ No newline at end of file
+
+	fn set_rumble(&mut self, enabled: bool) {
+		if let Some(controller) = &mut self.controller {
+			let strength = if enabled { 0xFFFF } else { 0 };
+
+			// The motor is kept running until told otherwise
+			controller.set_rumble(strength, strength, u32::MAX).ok();
+		}
+	}
+
+	fn queue_samples(&mut self, samples: &[f32]) {
+		if let Some(queue) = &self.audio {
+			queue.queue(samples);
+		}
+
+		if let Some((writer, path)) = &mut self.recording {
+			if let Err(e) = writer.write_samples(samples) {
+				eprintln!("Couldn't write {}: {}", path.display(), e);
+
+				// Keep what was recorded so far readable
+				self.stop_recording();
+			}
+		}
+	}
+}
+
+fn keycode_to_button(keycode: Keycode) -> Option<Button> {
+	match keycode {
+		Keycode::Right => Some(Button::Right),
+		Keycode::Left => Some(Button::Left),
+		Keycode::Up => Some(Button::Up),
+		Keycode::Down => Some(Button::Down),
+		Keycode::X => Some(Button::A),
+		Keycode::Z => Some(Button::B),
+		Keycode::Backspace => Some(Button::Select),
+		Keycode::Return => Some(Button::Start),
+		_ => None,
+	}
+}
+
+fn keycode_to_channel(keycode: Keycode) -> Option<Channel> {
+	match keycode {
+		Keycode::Num1 => Some(Channel::Square1),
+		Keycode::Num2 => Some(Channel::Square2),
+		Keycode::Num3 => Some(Channel::Wave),
+		Keycode::Num4 => Some(Channel::Noise),
+		_ => None,
+	}
+}
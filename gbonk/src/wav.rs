@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Writes interleaved stereo samples to a 16-bit PCM .wav file
+pub struct WavWriter {
+	file: BufWriter<File>,
+	data_size: u32,
+}
+
+impl WavWriter {
+	pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavWriter> {
+		let mut file = BufWriter::new(File::create(path)?);
+
+		// The sizes are filled in once the recording is done.
+		file.write_all(b"RIFF")?;
+		file.write_all(&0u32.to_le_bytes())?;
+		file.write_all(b"WAVE")?;
+
+		file.write_all(b"fmt ")?;
+		file.write_all(&16u32.to_le_bytes())?;
+		file.write_all(&1u16.to_le_bytes())?;
+		file.write_all(&2u16.to_le_bytes())?;
+		file.write_all(&sample_rate.to_le_bytes())?;
+		file.write_all(&(sample_rate * 4).to_le_bytes())?;
+		file.write_all(&4u16.to_le_bytes())?;
+		file.write_all(&16u16.to_le_bytes())?;
+
+		file.write_all(b"data")?;
+		file.write_all(&0u32.to_le_bytes())?;
+
+		Ok(WavWriter {
+			file,
+			data_size: 0,
+		})
+	}
+
+	pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+		for &sample in samples {
+			let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+			self.file.write_all(&value.to_le_bytes())?;
+		}
+
+		self.data_size += (samples.len() * 2) as u32;
+		Ok(())
+	}
+
+	pub fn finish(mut self) -> io::Result<()> {
+		self.file.seek(SeekFrom::Start(4))?;
+		self.file.write_all(&(36 + self.data_size).to_le_bytes())?;
+		self.file.seek(SeekFrom::Start(40))?;
+		self.file.write_all(&self.data_size.to_le_bytes())?;
+		self.file.flush()
+	}
+}